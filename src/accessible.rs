@@ -0,0 +1,372 @@
+//! A front end for screen readers. Nothing is drawn: commands are read a line at a time, and
+//! everything is reported back as plain lines of text, so that each new line can be read out as
+//! it comes.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    board::{Board, CellCategory, CellState, Error},
+    game::{Session, State},
+    opts::Def,
+    save,
+    stats::{Record, Store},
+};
+
+const HELP: [&str; 13] = [
+    "up, down, left, right (or k, j, h, l), followed by an optional count: move the cursor",
+    "go X Y: move the cursor to column X, row Y, counting from 0",
+    "look, or an empty line: describe the cursor cell and its neighbours",
+    "describe row [Y], describe column [X]: describe a whole row or column",
+    "dig (or u): reveal the cursor cell, or chord on a number",
+    "mark (or m): cycle the marks on the cursor cell, or flag chord on a number",
+    "chord: reveal around a number whose flags are all placed",
+    "flag chord: flag around a number whose remaining cells must all be mines",
+    "probe: ask for help when there are no safe moves left",
+    "status: say how the game is going",
+    "save: save the game to be picked up again later",
+    "help: list these commands",
+    "quit: leave the game",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Command {
+    // Columns and rows to move by.
+    Move(isize, isize),
+    Go(usize, usize),
+    Look,
+    Row(Option<usize>),
+    Column(Option<usize>),
+    Dig,
+    Mark,
+    Chord,
+    FlagChord,
+    Probe,
+    Status,
+    Save,
+    Help,
+    Quit,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<_> = line.split_whitespace().map(|w| w.to_lowercase()).collect();
+    let words: Vec<_> = words.iter().map(String::as_str).collect();
+    let number = |word: &str| word.parse::<usize>().map_err(|_| format!("{} is not a number.", word));
+    let step = |dx: isize, dy: isize, count: &[&str]| match count {
+        [] => Ok(Command::Move(dx, dy)),
+        [n] => {
+            let n = isize::try_from(number(n)?).map_err(|_| format!("{} is too far to move.", n))?;
+            Ok(Command::Move(dx * n, dy * n))
+        },
+        _ => Err("Moves take at most one count.".to_owned()),
+    };
+    match words.as_slice() {
+        [] | ["look"] => Ok(Command::Look),
+        ["up", count @ ..] | ["k", count @ ..] => step(0, -1, count),
+        ["down", count @ ..] | ["j", count @ ..] => step(0, 1, count),
+        ["left", count @ ..] | ["h", count @ ..] => step(-1, 0, count),
+        ["right", count @ ..] | ["l", count @ ..] => step(1, 0, count),
+        ["go", x, y] => Ok(Command::Go(number(x)?, number(y)?)),
+        ["describe", "row"] | ["row"] => Ok(Command::Row(None)),
+        ["describe", "row", y] | ["row", y] => Ok(Command::Row(Some(number(y)?))),
+        ["describe", "column"] | ["column"] => Ok(Command::Column(None)),
+        ["describe", "column", x] | ["column", x] => Ok(Command::Column(Some(number(x)?))),
+        ["dig"] | ["u"] => Ok(Command::Dig),
+        ["mark"] | ["m"] => Ok(Command::Mark),
+        ["chord"] => Ok(Command::Chord),
+        ["flag", "chord"] => Ok(Command::FlagChord),
+        ["probe"] => Ok(Command::Probe),
+        ["status"] => Ok(Command::Status),
+        ["save"] => Ok(Command::Save),
+        ["help"] => Ok(Command::Help),
+        ["quit"] | ["q"] => Ok(Command::Quit),
+        _ => Err(format!("Unknown command `{}`. Type help for a list of commands.", line.trim())),
+    }
+}
+
+fn describe_cell(board: &Board, (x, y): (usize, usize)) -> String {
+    let cell = board.cells[y][x];
+    match (cell.state, cell.category) {
+        (CellState::Hidden, _) => "hidden".to_owned(),
+        (CellState::Unsure, _) => "unsure".to_owned(),
+        (CellState::Marked(1), _) => "flagged".to_owned(),
+        (CellState::Marked(n), _) => format!("{} flags", n),
+        (CellState::Visible, CellCategory::Mine(_)) => "mine".to_owned(),
+        (CellState::Visible, CellCategory::Empty(None)) => "empty".to_owned(),
+        (CellState::Visible, CellCategory::Empty(Some(n))) => n.to_string(),
+    }
+}
+
+// Runs of the same description are grouped, e.g. "3 hidden, 1, 2, 4 empty".
+fn describe_line(board: &Board, locs: impl Iterator<Item = (usize, usize)>) -> String {
+    let mut runs: Vec<(String, usize)> = vec![];
+    for loc in locs {
+        let text = describe_cell(board, loc);
+        match runs.last_mut() {
+            Some((last, n)) if *last == text => *n += 1,
+            _ => runs.push((text, 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(text, n)| if n == 1 { text } else { format!("{} {}", n, text) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_neighbourhood(board: &Board, loc @ (x, y): (usize, usize)) -> String {
+    const NEIGHBOURS: [(isize, isize, &str); 8] = [
+        (0, -1, "up"),
+        (1, -1, "up right"),
+        (1, 0, "right"),
+        (1, 1, "down right"),
+        (0, 1, "down"),
+        (-1, 1, "down left"),
+        (-1, 0, "left"),
+        (-1, -1, "up left"),
+    ];
+    let around: Vec<_> = NEIGHBOURS.iter()
+        .filter_map(|(dx, dy, name)| {
+            let neighbour = ((x as isize + dx) as usize, (y as isize + dy) as usize);
+            if board.is_loc(neighbour) {
+                Some(format!("{} {}", name, describe_cell(board, neighbour)))
+            } else {
+                None
+            }
+        })
+        .collect();
+    format!("({}, {}) {}. Around it: {}.", x, y, describe_cell(board, loc), around.join(", "))
+}
+
+// What a move did, found by comparing the board from before and after it.
+fn describe_changes(before: &Board, after: &Board) -> Vec<String> {
+    let (mut opened, mut exploded, mut marked) = (0, vec![], vec![]);
+    for loc @ (x, y) in after.all_locs() {
+        let (was, is) = (before.cells[y][x], after.cells[y][x]);
+        if was.state == is.state {
+            continue;
+        }
+        if is.is_exploded() {
+            exploded.push(loc);
+        } else if is.state == CellState::Visible {
+            opened += 1;
+        } else {
+            marked.push(loc);
+        }
+    }
+
+    let mut lines = vec![];
+    match opened {
+        0 => (),
+        1 => lines.push("Opened 1 cell.".to_owned()),
+        n => lines.push(format!("Opened {} cells.", n)),
+    }
+    for (x, y) in exploded {
+        lines.push(format!("Hit a mine at ({}, {}).", x, y));
+    }
+    // Auto-flag can place a lot of flags at once, which would be tiring to listen to one by one.
+    if marked.len() > 4 {
+        lines.push(format!("Marks changed on {} cells.", marked.len()));
+    } else {
+        for loc @ (x, y) in marked {
+            lines.push(format!("({}, {}) is now {}.", x, y, describe_cell(after, loc)));
+        }
+    }
+    if lines.is_empty() {
+        lines.push("Nothing changed.".to_owned());
+    }
+    lines
+}
+
+fn describe_status(session: &Session) -> String {
+    let elapsed = session.elapsed().as_secs();
+    format!(
+        "{}. {} mines left, {} of {} lives left, {} probes used, {} minutes {} seconds on the clock.",
+        session.state(),
+        session.remaining_mines(),
+        session.lives(),
+        session.initial_lives(),
+        session.probes(),
+        elapsed / 60,
+        elapsed % 60,
+    )
+}
+
+pub struct Accessible<'a, W: Write> {
+    pub output: &'a mut W,
+    pub stats: &'a mut Option<Store>,
+    pub save_path: &'a Option<PathBuf>,
+}
+
+impl<'a, W: Write> Accessible<'a, W> {
+    fn say(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", line)?;
+        self.output.flush()
+    }
+
+    // Plays until the game is over, the player quits, or the input runs out.
+    pub fn play<R: BufRead>(&mut self, input: R, def: &Def, session: &mut Session) -> io::Result<()> {
+        let mut cursor = (0, 0);
+        self.say(&format!(
+            "{}, {} columns by {} rows. Type help for a list of commands.",
+            def,
+            session.board.w(),
+            session.board.h(),
+        ))?;
+        self.say(&describe_neighbourhood(&session.board, cursor))?;
+
+        for line in input.lines() {
+            let command = match parse_command(&line?) {
+                Ok(command) => command,
+                Err(e) => {
+                    self.say(&e)?;
+                    continue;
+                },
+            };
+            let (before, lives) = (session.board.clone(), session.lives());
+            let res = match command {
+                Command::Move(dx, dy) => {
+                    let (w, h) = (session.board.w() as isize, session.board.h() as isize);
+                    let x = (cursor.0 as isize).saturating_add(dx).max(0).min(w - 1);
+                    let y = (cursor.1 as isize).saturating_add(dy).max(0).min(h - 1);
+                    cursor = (x as usize, y as usize);
+                    self.say(&describe_neighbourhood(&session.board, cursor))?;
+                    continue;
+                },
+                Command::Go(x, y) => {
+                    if session.board.is_loc((x, y)) {
+                        cursor = (x, y);
+                        self.say(&describe_neighbourhood(&session.board, cursor))?;
+                    } else {
+                        self.say("That is off the board.")?;
+                    }
+                    continue;
+                },
+                Command::Look => {
+                    self.say(&describe_neighbourhood(&session.board, cursor))?;
+                    continue;
+                },
+                Command::Row(y) => {
+                    let y = y.unwrap_or(cursor.1);
+                    if y >= session.board.h() {
+                        self.say("That is off the board.")?;
+                        continue;
+                    }
+                    let line = describe_line(&session.board, (0..session.board.w()).map(|x| (x, y)));
+                    self.say(&format!("Row {}: {}.", y, line))?;
+                    continue;
+                },
+                Command::Column(x) => {
+                    let x = x.unwrap_or(cursor.0);
+                    if x >= session.board.w() {
+                        self.say("That is off the board.")?;
+                        continue;
+                    }
+                    let line = describe_line(&session.board, (0..session.board.h()).map(|y| (x, y)));
+                    self.say(&format!("Column {}: {}.", x, line))?;
+                    continue;
+                },
+                Command::Status => {
+                    self.say(&describe_status(session))?;
+                    continue;
+                },
+                Command::Save => {
+                    let message = match self.save_path {
+                        Some(path) => match save::save(path, def, session) {
+                            Ok(()) => format!("Saved to {}.", path.display()),
+                            Err(e) => format!("Could not save to {}: {}", path.display(), e),
+                        },
+                        None => "There is nowhere to save games to.".to_owned(),
+                    };
+                    self.say(&message)?;
+                    continue;
+                },
+                Command::Help => {
+                    for line in HELP.iter() {
+                        self.say(line)?;
+                    }
+                    continue;
+                },
+                Command::Quit => break,
+                Command::Dig => session.dig(cursor),
+                Command::Mark => session.mark(cursor),
+                Command::Chord => session.chord(cursor),
+                Command::FlagChord => session.flag_chord(cursor),
+                Command::Probe => session.launch_probe(),
+            };
+
+            match res {
+                Ok(()) | Err(Error::Dead(_)) => {
+                    for line in describe_changes(&before, &session.board) {
+                        self.say(&line)?;
+                    }
+                },
+                Err(Error::OOB) => self.say("That is off the board.")?,
+                Err(Error::Marked) => self.say("That cell is flagged, so it can't be dug.")?,
+            }
+            if session.lives() < lives && session.state() == State::Playing {
+                self.say(&format!("{} of {} lives left.", session.lives(), session.initial_lives()))?;
+            }
+
+            if let (Some(stats), Some(record)) = (&mut self.stats, Record::from_session(def, session)) {
+                stats.record(record);
+                if let Err(e) = stats.save() {
+                    self.say(&format!("Could not save statistics: {}", e))?;
+                }
+            }
+            match session.state() {
+                State::Playing => (),
+                State::Lost => {
+                    self.say("You have died!")?;
+                    if let Some(analysis) = session.analyze() {
+                        self.say(&analysis.to_string())?;
+                    }
+                    break;
+                },
+                State::Won => {
+                    self.say("Congratulations!")?;
+                    self.say(&describe_status(session))?;
+                    break;
+                },
+            }
+        }
+        self.say("Thanks for playing! Farewell.")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{board::Board, game::Session};
+    use super::{describe_line, describe_neighbourhood, Accessible};
+
+    #[test]
+    fn describe_test() {
+        let board = Board::from_save(b"x2HH\nH2FH\n").expect("board to parse correctly.");
+        assert_eq!(describe_line(&board, (0..4).map(|x| (x, 0))), "hidden, 2, 2 hidden");
+        assert_eq!(
+            describe_neighbourhood(&board, (1, 1)),
+            "(1, 1) 2. Around it: up 2, up right hidden, right flagged, left hidden, up left hidden.",
+        );
+    }
+
+    #[test]
+    fn play_test() {
+        let board = Board::from_save(b"xHHH\nHHHH\nHHHx\n").expect("board to parse correctly.");
+        let mut session = Session::new(board, 1);
+        let (mut output, mut stats) = (vec![], None);
+        let script = "right 3\ndig\nbogus\ngo 0 0\nmark\nrow\n";
+        Accessible { output: &mut output, stats: &mut stats, save_path: &None }
+            .play(script.as_bytes(), &"4,3,2".parse().expect("def to parse."), &mut session)
+            .expect("output to be written.");
+        let output = String::from_utf8(output).expect("output to be text.");
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines[2], "(3, 0) hidden. Around it: down hidden, down left hidden, left hidden.");
+        assert_eq!(lines[3], "Opened 6 cells.");
+        assert!(lines[4].starts_with("Unknown command `bogus`."));
+        assert_eq!(lines[5], "(0, 0) hidden. Around it: right 1, down right 1, down hidden.");
+        assert_eq!(lines[6], "(0, 0) is now flagged.");
+        assert_eq!(lines[7], "Row 0: flagged, 1, 2 empty.");
+    }
+}
@@ -0,0 +1,175 @@
+//! Plays whole games with nothing but the solver, to see how quickly it works on a given kind of
+//! board. Whenever the solver can't find anything certain, a random cell is dug. Mines that are hit
+//! are counted, and the game carries on regardless, so that every board is solved to the end.
+
+use std::time::{Duration, Instant};
+use rand::{Rng, RngCore, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{
+    board::{Board, BoardError, CellState, Error, Seed},
+    opts::Def,
+    parallel,
+    solver::{Backend, Budget, Solver},
+    util,
+};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub games: u64,
+    // Games won without hitting a single mine.
+    pub clean_wins: u64,
+    pub guesses: u64,
+    pub mines_hit: u64,
+    pub solves: u64,
+    // Solves that ran out of budget.
+    pub incomplete: u64,
+    pub solve_time: Duration,
+    pub slowest_solve: Duration,
+}
+
+impl Report {
+    fn add(&mut self, other: &Report) {
+        self.games += other.games;
+        self.clean_wins += other.clean_wins;
+        self.guesses += other.guesses;
+        self.mines_hit += other.mines_hit;
+        self.solves += other.solves;
+        self.incomplete += other.incomplete;
+        self.solve_time += other.solve_time;
+        self.slowest_solve = self.slowest_solve.max(other.slowest_solve);
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let per_solve = if self.solves == 0 { Duration::from_secs(0) } else { self.solve_time / self.solves as u32 };
+        writeln!(f, "Games: {} ({} won without hitting a mine)", self.games, self.clean_wins)?;
+        writeln!(f, "Guesses: {} ({} hit a mine)", self.guesses, self.mines_hit)?;
+        write!(
+            f,
+            "Solves: {} ({} out of budget) taking {:.3?} in total, {:.3?} on average, {:.3?} at most",
+            self.solves,
+            self.incomplete,
+            self.solve_time,
+            per_solve,
+            self.slowest_solve,
+        )
+    }
+}
+
+fn play(mut board: Board, rng: &mut impl Rng, backend: Backend, budget: Budget) -> Report {
+    let mut report = Report {
+        games: 1,
+        ..Report::default()
+    };
+    while !board.is_all_but_mines_revealed() {
+        let start = Instant::now();
+        let mut solver = Solver::with_backend(&board, backend);
+        solver.set_budget(budget);
+        let solution = solver.solve();
+        let elapsed = start.elapsed();
+        report.solves += 1;
+        report.solve_time += elapsed;
+        report.slowest_solve = report.slowest_solve.max(elapsed);
+
+        let mut to_dig = vec![];
+        if let Ok(solution) = solution {
+            if !solution.complete {
+                report.incomplete += 1;
+            }
+            let known = solution.known;
+            for loc in known.mines {
+                board.flag(loc).expect("solver to only report locations on the board.");
+            }
+            to_dig.extend(known.empty);
+        }
+        if to_dig.is_empty() {
+            let hidden: Vec<_> = board.all_locs()
+                .filter(|(x, y)| board.cells[*y][*x].state == CellState::Hidden)
+                .collect();
+            if hidden.is_empty() {
+                break;
+            }
+            report.guesses += 1;
+            to_dig.push(hidden[rng.gen_range(0, hidden.len())]);
+        }
+        for loc @ (x, y) in to_dig {
+            // Digging a number that an earlier dig already opened would chord it instead.
+            if board.cells[y][x].state == CellState::Visible {
+                continue;
+            }
+            if let Err(Error::Dead(n)) = board.dig(loc) {
+                report.mines_hit += n as u64;
+            }
+        }
+    }
+    if report.mines_hit == 0 {
+        report.clean_wins = 1;
+    }
+    report
+}
+
+// Every game gets its own board and guesses, drawn from `seed`, so that runs can be repeated. Games
+// are played in parallel when they can be, without changing anything but the timings.
+pub fn run(
+    def: &Def,
+    capacity: u8,
+    games: u64,
+    seed: Seed,
+    backend: Backend,
+    budget: Budget,
+) -> Result<Report, BoardError> {
+    let (dim, mines) = def.dim_and_mines();
+    let mut seeds = Xoshiro256PlusPlus::from_seed(seed);
+    let mut boards = vec![];
+    for _ in 0..games {
+        let mut board_seed = Seed::default();
+        seeds.fill_bytes(&mut board_seed);
+        boards.push((Board::new_seeded(dim, mines, capacity, board_seed)?, board_seed));
+    }
+    let reports = parallel::map(boards, |(board, board_seed)| {
+        play(board, &mut Xoshiro256PlusPlus::from_seed(board_seed), backend, budget)
+    });
+    let mut report = Report::default();
+    for game in &reports {
+        report.add(game);
+    }
+    Ok(report)
+}
+
+pub fn parse_seed(hex: &str) -> Option<Seed> {
+    let bytes = util::from_hex(hex)?;
+    let mut seed = Seed::default();
+    if bytes.len() != seed.len() {
+        return None;
+    }
+    seed.copy_from_slice(&bytes);
+    Some(seed)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parallel, solver::{Backend, Budget}};
+    use super::run;
+
+    #[test]
+    fn run_test() {
+        let def = "9,9,10".parse().expect("def to parse.");
+        let play = || run(&def, 1, 5, [7; 32], Backend::Linking, Budget::unlimited());
+        let report = play().expect("boards to be created.");
+        assert_eq!(report.games, 5);
+        assert!(report.solves >= 5);
+        assert_eq!(report.incomplete, 0);
+        // The same seed plays the same games.
+        let again = play().expect("boards to be created.");
+        assert_eq!((report.guesses, report.mines_hit, report.solves), (again.guesses, again.mines_hit, again.solves));
+        // No matter how many threads play them.
+        for threads in 1..=3 {
+            let spread = parallel::with_threads(Some(threads), play)
+                .expect("thread pool to be built.")
+                .expect("boards to be created.");
+            assert_eq!((report.guesses, report.mines_hit, report.solves), (spread.guesses, spread.mines_hit, spread.solves));
+        }
+    }
+}
@@ -2,18 +2,48 @@ use itertools::Itertools;
 use rand::{Rng, RngCore, SeedableRng, distributions::Uniform, rngs::OsRng};
 use rand_xoshiro::Xoshiro256PlusPlus as BaseRng;
 
-use crate::solver::Solver;
+pub type Seed = <BaseRng as SeedableRng>::Seed;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Error {
     OOB,
-    Dead,
+    // Number of mines that were set off.
+    Dead(u32),
     Marked,
 }
 
+// Why a set of cells, or a save, doesn't make a board.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BoardError {
+    // The byte, and the column and row it was found at.
+    UnknownCell(u8, (usize, usize)),
+    // The first row that isn't as wide as the ones before it.
+    Ragged(usize),
+    Empty,
+    Capacity(u8),
+    // A cell with more mines or flags than it has room for, or a count of none.
+    Overfull((usize, usize)),
+    // The number of mines asked for, and the most that the board has room for.
+    TooManyMines(u64, u64),
+}
+
+impl std::fmt::Display for BoardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCell(byte, (x, y)) => write!(f, "Unknown cell {:?} at ({}, {}).", *byte as char, x, y),
+            Self::Ragged(y) => write!(f, "Row {} is a different width to the ones before it.", y),
+            Self::Empty => write!(f, "Board has no cells."),
+            Self::Capacity(n) => write!(f, "Cells can't hold {} mines, only 1 to {}.", n, MAX_CAPACITY),
+            Self::Overfull((x, y)) => write!(f, "Cell ({}, {}) has a bad mine or flag count.", x, y),
+            Self::TooManyMines(mines, room) => write!(f, "Only {} mines fit, not {}.", room, mines),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum CellCategory {
-    Mine,
+    // Number of mines in the cell. Always at least one.
+    Mine(u8),
     Empty(Option<u8>),
 }
 
@@ -26,7 +56,10 @@ impl Default for CellCategory {
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum CellState {
     Hidden,
-    Marked,
+    // Number of flags placed on the cell. Always at least one.
+    Marked(u8),
+    // The player isn't sure. Otherwise behaves exactly like a hidden cell.
+    Unsure,
     Visible,
 }
 
@@ -45,15 +78,28 @@ pub struct Cell {
 }
 
 impl Cell {
-    fn to_char(&self) -> char {
-        match self.state {
-            CellState::Hidden => '\u{25A1}',
-            CellState::Marked => 'F',
-            CellState::Visible => match self.category {
-                CellCategory::Mine => 'M',
-                CellCategory::Empty(None) => '\u{25A0}',
-                CellCategory::Empty(Some(n)) => (b'0' + n) as char,
-            },
+    pub fn is_mine(&self) -> bool {
+        match self.category {
+            CellCategory::Mine(_) => true,
+            CellCategory::Empty(_) => false,
+        }
+    }
+
+    pub fn is_marked(&self) -> bool {
+        matches!(self.state, CellState::Marked(_))
+    }
+
+    pub fn is_exploded(&self) -> bool {
+        self.state == CellState::Visible && self.is_mine()
+    }
+
+    // Number of mines the player is presumed to know about in this cell. This is either the number
+    // of flags placed on it or, if it exploded, the mines it actually held.
+    pub fn known_mines(&self) -> u8 {
+        match (self.state, self.category) {
+            (CellState::Marked(n), _) => n,
+            (CellState::Visible, CellCategory::Mine(n)) => n,
+            _ => 0,
         }
     }
 }
@@ -65,13 +111,13 @@ pub enum Dim {
 }
 
 impl Dim {
-    fn w(&self) -> usize {
+    pub fn w(&self) -> usize {
         match self {
             Dim::Square(n) => *n,
             Dim::Rect(n, _) => *n,
         }
     }
-    fn h(&self) -> usize {
+    pub fn h(&self) -> usize {
         match self {
             Dim::Square(n) => *n,
             Dim::Rect(_, n) => *n,
@@ -79,10 +125,26 @@ impl Dim {
     }
 }
 
-#[derive(Debug)]
+// The most mines a single cell may hold. Any more and the surrounding counts can no longer be
+// displayed as a single character.
+pub const MAX_CAPACITY: u8 = 4;
+
+// A cell whose state changed, and the state it had before.
+pub type Change = ((usize, usize), CellState);
+
+#[derive(Debug, Clone)]
 pub struct Board {
     pub cells: Box<[Box<[Cell]>]>,
     dims: (usize, usize),
+    // Maximum number of mines per cell. Classic minesweeper has a capacity of 1.
+    capacity: u8,
+    // Whether marking cycles through the unsure state before returning to hidden.
+    unsure_marks: bool,
+    // Only present if the mines were randomly placed.
+    seed: Option<Seed>,
+    // Every change made by a move since they were last taken, so that anything kept alongside the
+    // board can catch up without looking at every cell.
+    changes: Vec<Change>,
 }
 
 // Helpers
@@ -151,49 +213,91 @@ impl Board {
         self.dims.1
     }
 
-    pub fn all_locs(&self) -> impl Iterator<Item=(usize, usize)> {
-        (0..self.h()).cartesian_product(0..self.w())
+    pub fn capacity(&self) -> u8 {
+        self.capacity
     }
-}
 
-// Constructors
-impl Board {
-    pub fn beginner() -> Result<Self, ()> {
-        Self::new(Dim::Square(9), 10)
+    pub fn seed(&self) -> Option<Seed> {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: Option<Seed>) {
+        self.seed = seed;
+    }
+
+    pub fn unsure_marks(&self) -> bool {
+        self.unsure_marks
     }
 
-    pub fn intermediate() -> Result<Self, ()> {
-        Self::new(Dim::Square(16), 40)
+    pub fn set_unsure_marks(&mut self, enabled: bool) {
+        self.unsure_marks = enabled;
     }
 
-    pub fn advanced() -> Result<Self, ()> {
-        Self::new(Dim::Rect(30, 16), 99)
+    // Row by row, as (x, y).
+    pub fn all_locs(&self) -> impl Iterator<Item=(usize, usize)> {
+        (0..self.h()).cartesian_product(0..self.w()).map(|(y, x)| (x, y))
     }
+}
 
-    pub fn new(dim: Dim, num_mines: u64) -> Result<Self, ()> {
+// Constructors
+impl Board {
+    pub fn new(dim: Dim, num_mines: u64, capacity: u8) -> Result<Self, BoardError> {
         let mut seed = [0; 32];
         OsRng.fill_bytes(&mut seed);
-        Self::new_seeded(dim, num_mines, seed)
+        Self::new_seeded(dim, num_mines, capacity, seed)
     }
 
-    pub fn new_seeded(dim: Dim, num_mines: u64, seed: <BaseRng as SeedableRng>::Seed) -> Result<Self, ()> {
+    pub fn new_seeded(
+        dim: Dim,
+        num_mines: u64,
+        capacity: u8,
+        seed: Seed,
+    ) -> Result<Self, BoardError> {
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(BoardError::Capacity(capacity));
+        }
+        if dim.w() == 0 || dim.h() == 0 {
+            return Err(BoardError::Empty);
+        }
+        // There'd be no room left to put the rest.
+        Self::check_room(dim, num_mines, capacity)?;
         let mut randos = BaseRng::from_seed(seed);
 
-        let mut seed = [[0; 32]; 2];
-        randos.fill_bytes(&mut seed[0]);
-        randos.fill_bytes(&mut seed[1]);
-        let x_rng = BaseRng::from_seed(seed[0]);
-        let y_rng = BaseRng::from_seed(seed[1]);
+        let mut sub_seeds = [[0; 32]; 2];
+        randos.fill_bytes(&mut sub_seeds[0]);
+        randos.fill_bytes(&mut sub_seeds[1]);
+        let x_rng = BaseRng::from_seed(sub_seeds[0]);
+        let y_rng = BaseRng::from_seed(sub_seeds[1]);
 
         let x_range = Uniform::from(0..dim.w());
         let y_range = Uniform::from(0..dim.h());
 
         let (x_randos, y_randos) = (x_rng.sample_iter(x_range), y_rng.sample_iter(y_range));
 
-        Self::new_fixed(dim, x_randos.zip(y_randos).take(num_mines as usize))
+        // Draws that land on a full cell are thrown away, rather than losing the mine.
+        let w = dim.w();
+        let mut counts = vec![0; w * dim.h()];
+        let locs = x_randos.zip(y_randos)
+            .filter(|(x, y)| {
+                let count = &mut counts[y * w + x];
+                if *count == capacity {
+                    return false;
+                }
+                *count += 1;
+                true
+            })
+            .take(num_mines as usize);
+
+        let mut board = Self::new_fixed(dim, capacity, locs)?;
+        board.seed = Some(seed);
+        Ok(board)
     }
 
-    pub fn new_fixed<I>(dim: Dim, locs: I) -> Result<Self, ()> where I: IntoIterator<Item = (usize, usize)> {
+    // Each occurrence of a location adds a mine to it, up until the cell is at capacity.
+    pub fn new_fixed<I>(dim: Dim, capacity: u8, locs: I) -> Result<Self, BoardError> where I: IntoIterator<Item = (usize, usize)> {
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(BoardError::Capacity(capacity));
+        }
         let (w, h) = (dim.w(), dim.h());
         let mut cells = vec![vec![Cell::default(); w as usize]; h as usize]
             .into_iter()
@@ -202,32 +306,56 @@ impl Board {
             .into_boxed_slice();
 
         for loc in locs.into_iter() {
-            cells[loc.1][loc.0].category = CellCategory::Mine;
+            let category = &mut cells[loc.1][loc.0].category;
+            *category = match *category {
+                CellCategory::Mine(n) if n < capacity => CellCategory::Mine(n + 1),
+                CellCategory::Mine(n) => CellCategory::Mine(n),
+                CellCategory::Empty(_) => CellCategory::Mine(1),
+            };
         }
 
-        Self::from_cells(cells)
+        Self::from_cells(cells, capacity)
+    }
+
+    // Whether a board of this size has room for every mine. Boards too large to count their cells
+    // always do.
+    pub fn check_room(dim: Dim, num_mines: u64, capacity: u8) -> Result<(), BoardError> {
+        let room = (dim.w() as u64).checked_mul(dim.h() as u64).and_then(|n| n.checked_mul(capacity as u64));
+        match room {
+            Some(room) if num_mines > room => Err(BoardError::TooManyMines(num_mines, room)),
+            _ => Ok(()),
+        }
     }
 
-    #[cfg(test)]
-    pub fn from_save(cells: &[u8]) -> Result<Self, ()> {
-        let mut board = {
-            let mut board = vec![];
+    // The save format has one character per cell, and one line per row. Numbers are recalculated,
+    // so any digit (or a space) is a revealed empty cell, `+` included. Otherwise:
+    // `H`: hidden, `x`: hidden mine, `!`: exploded mine,
+    // `f`: flagged, `F`: flagged mine, `u`: unsure, `U`: unsure mine.
+    // Every mine and flag counts as one, so boards read this way have a capacity of 1.
+    pub fn from_save(cells: &[u8]) -> Result<Self, BoardError> {
+        let board = {
+            let mut board: Vec<Box<[Cell]>> = vec![];
             let mut row = vec![];
+            // Rows are checked as they end, so a ragged board is turned away at the first bad row.
+            let end_row = |board: &mut Vec<Box<[Cell]>>, row: Vec<Cell>| {
+                if matches!(board.first(), Some(first) if first.len() != row.len()) {
+                    return Err(BoardError::Ragged(board.len()));
+                }
+                board.push(row.into_boxed_slice());
+                Ok(())
+            };
             for cell in cells {
                 match cell {
                     b'\r' => {} // Ignore any part of newline other than the actual newline character.
-                    b'\n' => {
-                        board.push(row.into_boxed_slice());
-                        row = vec![];
-                    }
+                    b'\n' => end_row(&mut board, std::mem::take(&mut row))?,
                     b'!' => {
                         row.push(Cell {
                             state: CellState::Visible,
-                            category: CellCategory::Mine,
+                            category: CellCategory::Mine(1),
                             scratch: false,
                         })
                     }
-                    b'0'..=b'9' | b' ' => {
+                    b'0'..=b'9' | b' ' | b'+' => {
                         row.push(Cell {
                             state: CellState::Visible,
                             category: CellCategory::Empty(None),
@@ -237,7 +365,7 @@ impl Board {
                     b'x' => {
                         row.push(Cell {
                             state: CellState::Hidden,
-                            category: CellCategory::Mine,
+                            category: CellCategory::Mine(1),
                             scratch: false,
                         })
                     }
@@ -250,66 +378,158 @@ impl Board {
                     }
                     b'F' => {
                         row.push(Cell {
-                            state: CellState::Marked,
-                            category: CellCategory::Mine,
+                            state: CellState::Marked(1),
+                            category: CellCategory::Mine(1),
                             scratch: false,
                         })
                     }
                     b'f' => {
                         row.push(Cell {
-                            state: CellState::Marked,
+                            state: CellState::Marked(1),
                             category: CellCategory::Empty(None),
                             scratch: false,
                         })
                     }
-                    _ => return Err(()),
+                    b'U' => {
+                        row.push(Cell {
+                            state: CellState::Unsure,
+                            category: CellCategory::Mine(1),
+                            scratch: false,
+                        })
+                    }
+                    b'u' => {
+                        row.push(Cell {
+                            state: CellState::Unsure,
+                            category: CellCategory::Empty(None),
+                            scratch: false,
+                        })
+                    }
+                    _ => return Err(BoardError::UnknownCell(*cell, (row.len(), board.len()))),
                 }
             }
             if !row.is_empty() {
-                board.push(row.into_boxed_slice())
+                end_row(&mut board, row)?;
             }
             board.into_boxed_slice()
         };
 
-        // Validate board size.
-        let h = board.len();
-        let w = board.first().map_or(0, |v| v.len());
-        for row in board.iter() {
-            if row.len() != w {
-                return Err(());
+        Self::from_cells(board, 1)
+    }
+
+    // The opposite of `from_save`. Mine and flag counts are lost, and numbers above 9 are written
+    // as `+`.
+    pub fn to_save(&self) -> String {
+        let mut save = String::new();
+        for row in self.cells.iter() {
+            for cell in row.iter() {
+                save.push(match (cell.state, cell.category) {
+                    (CellState::Hidden, CellCategory::Mine(_)) => 'x',
+                    (CellState::Hidden, CellCategory::Empty(_)) => 'H',
+                    (CellState::Marked(_), CellCategory::Mine(_)) => 'F',
+                    (CellState::Marked(_), CellCategory::Empty(_)) => 'f',
+                    (CellState::Unsure, CellCategory::Mine(_)) => 'U',
+                    (CellState::Unsure, CellCategory::Empty(_)) => 'u',
+                    (CellState::Visible, CellCategory::Mine(_)) => '!',
+                    (CellState::Visible, CellCategory::Empty(None)) => ' ',
+                    (CellState::Visible, CellCategory::Empty(Some(n))) => std::char::from_digit(n as u32, 10)
+                        .unwrap_or('+'),
+                });
             }
+            save.push('\n');
         }
-
-        Self::from_cells(board)
+        save
     }
 
-    pub fn from_cells(cells: Box<[Box<[Cell]>]>) -> Result<Self, ()> {
+    pub fn from_cells(cells: Box<[Box<[Cell]>]>, capacity: u8) -> Result<Self, BoardError> {
+        if capacity == 0 || capacity > MAX_CAPACITY {
+            return Err(BoardError::Capacity(capacity));
+        }
         let h = cells.len();
         let w = cells.first().map_or(0, |v| v.len());
+        if w == 0 {
+            return Err(BoardError::Empty);
+        }
+        for (y, row) in cells.iter().enumerate() {
+            if row.len() != w {
+                return Err(BoardError::Ragged(y));
+            }
+            for (x, cell) in row.iter().enumerate() {
+                let mines = match cell.category {
+                    CellCategory::Mine(n) => Some(n),
+                    CellCategory::Empty(_) => None,
+                };
+                let flags = match cell.state {
+                    CellState::Marked(n) => Some(n),
+                    _ => None,
+                };
+                if mines.into_iter().chain(flags).any(|n| n == 0 || n > capacity) {
+                    return Err(BoardError::Overfull((x, y)));
+                }
+            }
+        }
         let mut board = Self {
             cells,
             dims: (w, h),
+            capacity,
+            unsure_marks: false,
+            seed: None,
+            changes: vec![],
         };
 
         for row in 0..h {
             for col in 0..w {
                 let category = board.cells[row][col].category;
-                if category == CellCategory::Mine {
+                if let CellCategory::Mine(_) = category {
                     continue
                 }
                 let surroundings = board.surroundings_of((col, row));
                 let nearby_bombs = surroundings
-                    .filter(|(x, y)| board.cells[*y][*x].category == CellCategory::Mine)
-                    .count() as u8;
-                if nearby_bombs == 0 {
-                    continue
-                }
-                board.cells[row][col].category = CellCategory::Empty(Some(nearby_bombs));
+                    .map(|(x, y)| match board.cells[y][x].category {
+                        CellCategory::Mine(n) => n,
+                        CellCategory::Empty(_) => 0,
+                    })
+                    .sum::<u8>();
+                board.cells[row][col].category = CellCategory::Empty(Some(nearby_bombs).filter(|n| *n != 0));
             }
         }
 
         Ok(board)
     }
+
+    // Hides every cell again, as if no moves had been made. Changes from before then are dropped,
+    // since there's nothing left to catch up with.
+    pub fn reset(&mut self) {
+        for row in self.cells.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.state = CellState::Hidden;
+            }
+        }
+        self.changes.clear();
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    // Puts the cells back the way they were before the changes, latest first.
+    pub fn revert(&mut self, changes: &[Change]) {
+        for ((x, y), state) in changes.iter().rev() {
+            self.cells[*y][*x].state = *state;
+        }
+    }
+
+    // Moves change cells through here, so that every change is kept track of.
+    fn set_state(&mut self, (x, y): (usize, usize), state: CellState) {
+        let cell = &mut self.cells[y][x];
+        if cell.state != state {
+            self.changes.push(((x, y), cell.state));
+            cell.state = state;
+        }
+    }
 }
 
 // Marking and digging.
@@ -321,42 +541,99 @@ impl Board {
             return Err(Error::OOB);
         }
 
-        let cell = &mut self.cells[y][x];
-        cell.state = match cell.state {
-            CellState::Hidden => CellState::Marked,
-            CellState::Marked => CellState::Hidden,
-            CellState::Visible => CellState::Visible,
+        let (capacity, unsure_marks) = (self.capacity, self.unsure_marks);
+        // Cycle through the flag counts (and possibly unsure) before returning to hidden.
+        let state = match self.cells[y][x].state {
+            CellState::Hidden => CellState::Marked(1),
+            CellState::Marked(n) if n < capacity => CellState::Marked(n + 1),
+            CellState::Marked(_) if unsure_marks => CellState::Unsure,
+            CellState::Marked(_) => CellState::Hidden,
+            CellState::Unsure => CellState::Hidden,
+            // Marking a revealed number flag chords on it instead.
+            CellState::Visible => return self.flag_chord_at(point),
         };
+        self.set_state(point, state);
+        Ok(())
+    }
+
+    // Fills a hidden cell with flags. Does nothing to anything else.
+    pub fn flag(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        let (x, y) = point;
+        if !self.is_loc(point) {
+            return Err(Error::OOB);
+        }
+        let state = self.cells[y][x].state;
+        if state == CellState::Hidden || state == CellState::Unsure {
+            self.set_state(point, CellState::Marked(self.capacity));
+        }
+        Ok(())
+    }
+
+    // Flag chording anything other than a revealed number does nothing.
+    pub fn flag_chord_at(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        let (x, y) = point;
+        if !self.is_loc(point) {
+            return Err(Error::OOB);
+        }
+        let cell = self.cells[y][x];
+        match (cell.state, cell.category) {
+            (CellState::Visible, CellCategory::Empty(Some(num_mines))) => self.flag_chord(point, num_mines),
+            _ => Ok(()),
+        }
+    }
+
+    // The opposite of chording. Flags every hidden neighbour, but only if they must all be full of
+    // mines to make up the number.
+    fn flag_chord(&mut self, point: (usize, usize), target_num_mines: u8) -> Result<(), Error> {
+        let surroundings: Vec<_> = self.surroundings_of(point)
+            .collect();
+        // Exploded mines count as flags.
+        let marked_mines = surroundings.iter()
+            .map(|(x, y)| self.cells[*y][*x].known_mines())
+            .sum::<u8>();
+        let hidden: Vec<_> = surroundings.into_iter()
+            .filter(|(x, y)| {
+                let state = self.cells[*y][*x].state;
+                state == CellState::Hidden || state == CellState::Unsure
+            })
+            .collect();
+        if hidden.is_empty() || marked_mines + hidden.len() as u8 * self.capacity != target_num_mines {
+            return Ok(());
+        }
+        for loc in hidden {
+            self.flag(loc)?;
+        }
         Ok(())
     }
 
     fn chord(&mut self, point: (usize, usize), target_num_mines: u8) -> Result<(), Error> {
         let surroundings: Vec<_> = self.surroundings_of(point)
             .collect();
+        // Exploded mines count as flags.
         let marked_mines = surroundings.iter()
-            .filter(|(x, y)| self.cells[*y][*x].state == CellState::Marked)
-            .count() as u8;
+            .map(|(x, y)| self.cells[*y][*x].known_mines())
+            .sum::<u8>();
         if marked_mines != target_num_mines {
             return Ok(());
         }
         let unmarked_mines = surroundings.iter()
             .filter(|(x, y)| {
                 let cell = &mut self.cells[*y][*x];
-                (cell.state != CellState::Marked) && (cell.category == CellCategory::Mine)
+                !cell.is_marked() && !cell.is_exploded() && cell.is_mine()
             })
-            .count() as u8;
+            .count() as u32;
         for (x, y) in surroundings.into_iter() {
-            let cell = &mut self.cells[y][x];
-            if cell.state != CellState::Marked {
+            let cell = self.cells[y][x];
+            if !cell.is_marked() && cell.state != CellState::Visible {
                 if cell.category == CellCategory::Empty(None) {
                     self.dig_region((x, y))?;
                 } else {
-                    cell.state = CellState::Visible;
+                    self.set_state((x, y), CellState::Visible);
                 }
             }
         }
         if unmarked_mines != 0 {
-            Err(Error::Dead)
+            Err(Error::Dead(unmarked_mines))
         } else {
             Ok(())
         }
@@ -364,53 +641,72 @@ impl Board {
 
     fn dig_region(&mut self, start: (usize, usize)) -> Result<(), Error> {
         let mut scanning_locs = vec![start];
-        for y in 0..self.dims.1 {
-            for x in 0..self.dims.0 {
-                self.cells[y][x].scratch = false;
-            }
-        }
-        self.cells[start.1][start.0].state = CellState::Visible;
+        // Only the cells scanned are marked as such, so only they need clearing afterwards.
+        let mut scanned = vec![];
+        self.set_state(start, CellState::Visible);
         while let Some(loc) = scanning_locs.pop() {
-            self.surroundings_of(loc).for_each(|to_scan_loc @ (_, _)| {
-                let (x, y) = to_scan_loc;
+            for to_scan_loc @ (x, y) in self.surroundings_of(loc) {
                 let cell = &mut self.cells[y][x];
                 if let CellCategory::Empty(num_mines) = cell.category {
                     // Only reveal if no mines in surroundings.
-                    if num_mines.is_none() && cell.state != CellState::Marked && !cell.scratch {
+                    if num_mines.is_none() && !cell.is_marked() && !cell.scratch {
                         cell.scratch = true;
+                        scanned.push(to_scan_loc);
                         scanning_locs.push(to_scan_loc);
                     }
-                    if cell.state != CellState::Marked {
-                        cell.state = CellState::Visible;
+                    if !cell.is_marked() {
+                        self.set_state(to_scan_loc, CellState::Visible);
                     }
                 } else {
                     unimplemented!("Found a mine while flood filling an empty region. This should be impossible.");
                 }
-            });
+            }
+        }
+        for (x, y) in scanned {
+            self.cells[y][x].scratch = false;
         }
         Ok(())
     }
 
+    // Chording anything other than a revealed number does nothing.
+    pub fn chord_at(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        let (x, y) = point;
+        if !self.is_loc(point) {
+            return Err(Error::OOB);
+        }
+        let cell = self.cells[y][x];
+        match (cell.state, cell.category) {
+            (CellState::Visible, CellCategory::Empty(Some(num_mines))) => self.chord(point, num_mines),
+            _ => Ok(()),
+        }
+    }
+
     pub fn dig(&mut self, point: (usize, usize)) -> Result<(), Error> {
         let (x, y) = point;
         if !self.is_loc(point) {
             // TODO Consider replacing this error with an assert.
             return Err(Error::OOB);
         }
-        let cell = &mut self.cells[y][x];
-        if cell.state == CellState::Marked {
+        let cell = self.cells[y][x];
+        if cell.is_marked() {
             return Err(Error::Marked);
         }
 
         match cell.category {
-            CellCategory::Mine => Err(Error::Dead),
-            CellCategory::Empty(None) => if cell.state == CellState::Hidden {
+            // Digging an exploded mine does nothing, since it already cost the player.
+            CellCategory::Mine(_) => if cell.state != CellState::Visible {
+                self.set_state(point, CellState::Visible);
+                Err(Error::Dead(1))
+            } else {
+                Ok(())
+            },
+            CellCategory::Empty(None) => if cell.state != CellState::Visible {
                 self.dig_region(point)
             } else {
                 Ok(())
             },
-            CellCategory::Empty(Some(num_mines)) => if cell.state == CellState::Hidden {
-                cell.state = CellState::Visible;
+            CellCategory::Empty(Some(num_mines)) => if cell.state != CellState::Visible {
+                self.set_state(point, CellState::Visible);
                 Ok(())
             } else {
                 self.chord(point, num_mines)
@@ -426,7 +722,7 @@ impl Board {
         for row in 0..h {
             for col in 0..w {
                 let cell = self.cells[row][col];
-                if cell.category != CellCategory::Mine && cell.state != CellState::Visible {
+                if !cell.is_mine() && cell.state != CellState::Visible {
                     return false;
                 }
             }
@@ -434,31 +730,78 @@ impl Board {
         true
     }
 
-    pub fn launch_probe(&self) -> Result<(), Error> {
-        // Check for any 100% valid moves.
-        let valid_moves = Solver::new(self).calculate_known_cells()
-            .expect("player did not make a mistake. Which needs to be dealt with eventually, since humans always make mistakes. Except that one person. Yeah, that one.");
-        if valid_moves.is_some() {
-            Err(Error::Dead)
-        } else {
-            Ok(())
+    pub fn num_mines(&self) -> u64 {
+        self.cells.iter()
+            .flat_map(|row| row.iter())
+            .map(|cell| match cell.category {
+                CellCategory::Mine(n) => n as u64,
+                CellCategory::Empty(_) => 0,
+            })
+            .sum()
+    }
+
+    // Flags placed, plus any mines that exploded.
+    pub fn num_known_mines(&self) -> u64 {
+        self.cells.iter()
+            .flat_map(|row| row.iter())
+            .map(|cell| cell.known_mines() as u64)
+            .sum()
+    }
+
+    // The minimum number of clicks required to clear the board without chording, also known as
+    // the board's 3BV.
+    pub fn bbbv(&self) -> u64 {
+        let (w, h) = self.dims;
+        let mut covered = vec![vec![false; w]; h];
+        let mut clicks = 0;
+        // Every opening takes a single click, which also reveals its border.
+        for row in 0..h {
+            for col in 0..w {
+                if covered[row][col] || self.cells[row][col].category != CellCategory::Empty(None) {
+                    continue;
+                }
+                clicks += 1;
+                covered[row][col] = true;
+                let mut scanning_locs = vec![(col, row)];
+                while let Some(loc) = scanning_locs.pop() {
+                    for (x, y) in self.surroundings_of(loc) {
+                        if covered[y][x] {
+                            continue;
+                        }
+                        covered[y][x] = true;
+                        if self.cells[y][x].category == CellCategory::Empty(None) {
+                            scanning_locs.push((x, y));
+                        }
+                    }
+                }
+            }
+        }
+        // Every number that isn't on the border of an opening needs its own click.
+        for (cells, covered) in self.cells.iter().zip(covered.iter()) {
+            for (cell, covered) in cells.iter().zip(covered.iter()) {
+                if let CellCategory::Empty(Some(_)) = cell.category {
+                    if !covered {
+                        clicks += 1;
+                    }
+                }
+            }
         }
+        clicks
     }
 }
 
 impl Board {
-    pub fn display(&self, max_dims: (usize, usize), top_left: (usize, usize)) -> Result<Box<[Box<[char]>]>, ()> {
-        let rem_dims = (self.dims.0 - top_left.0, self.dims.1 - top_left.1);
+    pub fn display(&self, max_dims: (usize, usize), top_left: (usize, usize)) -> Result<Box<[Box<[Cell]>]>, ()> {
+        let rem_dims = (self.dims.0.saturating_sub(top_left.0), self.dims.1.saturating_sub(top_left.1));
         let true_dims = (max_dims.0.min(rem_dims.0), max_dims.1.min(rem_dims.1));
-        let mut snippet = vec![vec!['\u{25A1}'; true_dims.0]; true_dims.1]
+        let mut snippet = vec![vec![Cell::default(); true_dims.0]; true_dims.1]
             .into_iter()
             .map(|row| row.into_boxed_slice())
             .collect::<Vec<_>>()
             .into_boxed_slice();
         for row in 0..true_dims.1 {
             for col in 0..true_dims.0 {
-                let cell = &self.cells[row][col];
-                snippet[row][col] = cell.to_char();
+                snippet[row][col] = self.cells[top_left.1 + row][top_left.0 + col];
             }
         }
         Ok(snippet)
@@ -469,9 +812,292 @@ impl Board {
 mod test {
     use super::*;
 
+    // Anything from no mines up to a third of the room there is for them.
+    fn random_board(rng: &mut BaseRng) -> Board {
+        let dim = Dim::Rect(rng.gen_range(1, 13), rng.gen_range(1, 13));
+        let capacity = rng.gen_range(1, MAX_CAPACITY + 1);
+        let room = (dim.w() * dim.h()) as u64 * capacity as u64;
+        Board::new_seeded(dim, rng.gen_range(0, room / 3 + 1), capacity, rng.gen())
+            .expect("board to be created without a hitch.")
+    }
+
+    // Worked out separately from `surroundings_of`, so the two can be checked against each other.
+    fn around(board: &Board, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+        (-1..=1).cartesian_product(-1..=1)
+            .filter(|offset| *offset != (0, 0))
+            .map(|(dx, dy)| (x as isize + dx, y as isize + dy))
+            .filter(|(x, y)| *x >= 0 && *y >= 0)
+            .map(|(x, y)| (x as usize, y as usize))
+            .filter(|loc| board.is_loc(*loc))
+            .collect()
+    }
+
+    fn count_around(board: &Board, loc: (usize, usize)) -> u8 {
+        around(board, loc).into_iter()
+            .map(|(x, y)| match board.cells[y][x].category {
+                CellCategory::Mine(n) => n,
+                CellCategory::Empty(_) => 0,
+            })
+            .sum()
+    }
+
     #[test]
     fn from_save_test() {
         Board::from_save(include_bytes!("../testing/boards/basic.txt"))
             .expect("board to parse correctly from file.");
+        assert_eq!(Board::from_save(b"HHH\nHH\nHHH\n").unwrap_err(), BoardError::Ragged(1));
+        assert_eq!(Board::from_save(b"HH\nHz\n").unwrap_err(), BoardError::UnknownCell(b'z', (1, 1)));
+        assert_eq!(Board::from_save(b"\n").unwrap_err(), BoardError::Empty);
+    }
+
+    #[test]
+    fn display_test() {
+        let board = Board::from_save(b"HHHH\nHHHH\nHHHx\n")
+            .expect("board to parse correctly.");
+        let snippet = board.display((2, 2), (2, 1)).expect("display to be fine.");
+        assert_eq!(snippet.len(), 2);
+        assert_eq!(snippet[1][1].category, CellCategory::Mine(1));
+        assert_eq!(snippet[0][0].category, CellCategory::Empty(Some(1)));
+
+        // Only what's left of the board is returned.
+        let snippet = board.display((8, 8), (3, 2)).expect("display to be fine.");
+        assert_eq!(snippet.len(), 1);
+        assert_eq!(snippet[0].len(), 1);
+    }
+
+    #[test]
+    fn bbbv_test() {
+        // One opening, plus the number in the corner that it doesn't reach.
+        let board = Board::from_save(b"HHHHH\nxHHHH\nHHHHH\nHHHHH\nHHHHH\n")
+            .expect("board to parse correctly.");
+        assert_eq!(board.bbbv(), 2);
+        assert_eq!(board.num_mines(), 1);
+    }
+
+    #[test]
+    fn multi_mine_test() {
+        let mut board = Board::new_fixed(Dim::Square(3), 2, vec![(0, 0), (0, 0), (0, 0), (2, 2)])
+            .expect("board to be created without a hitch.");
+        assert_eq!(board.cells[0][0].category, CellCategory::Mine(2));
+        assert_eq!(board.cells[1][1].category, CellCategory::Empty(Some(3)));
+
+        let cycle: Vec<_> = (0..3)
+            .map(|_| {
+                board.mark((0, 0)).expect("location to be on the board.");
+                board.cells[0][0].state
+            })
+            .collect();
+        assert_eq!(cycle, vec![CellState::Marked(1), CellState::Marked(2), CellState::Hidden]);
+    }
+
+    #[test]
+    fn to_save_test() {
+        let save = b"x2H\nF4u\n!fU\n";
+        let board = Board::from_save(save).expect("board to parse correctly.");
+        assert_eq!(board.to_save().as_bytes(), &save[..]);
+    }
+
+    #[test]
+    fn flag_chord_test() {
+        let mut board = Board::from_save(b"HHH\nxx1\nHHH\n")
+            .expect("board to parse correctly.");
+        // Too many hidden neighbours to tell which is the mine.
+        board.flag_chord_at((2, 1)).expect("location to be on the board.");
+        assert_eq!(board.cells[1][1].state, CellState::Hidden);
+
+        for loc in [(1, 0), (2, 0), (1, 2), (2, 2)].iter().cloned() {
+            board.dig(loc).expect("location to be safe.");
+        }
+        board.mark((2, 1)).expect("location to be on the board.");
+        assert_eq!(board.cells[1][1].state, CellState::Marked(1));
+        assert_eq!(board.cells[1][0].state, CellState::Hidden);
+    }
+
+    #[test]
+    fn unsure_test() {
+        let mut board = Board::from_save(b"x1H\nH1H\n")
+            .expect("board to parse correctly.");
+        board.set_unsure_marks(true);
+        for _ in 0..2 {
+            board.mark((2, 0)).expect("location to be on the board.");
+        }
+        assert_eq!(board.cells[0][2].state, CellState::Unsure);
+
+        // Unsure cells are not flags, so they neither count towards nor block a chord.
+        board.dig((1, 0)).expect("chord to do nothing.");
+        assert_eq!(board.cells[0][2].state, CellState::Unsure);
+        board.mark((0, 0)).expect("location to be on the board.");
+        board.dig((1, 0)).expect("chord to not hit a mine.");
+        assert_eq!(board.cells[0][2].state, CellState::Visible);
+    }
+
+    #[test]
+    fn new_seeded_test() {
+        let mut rng = BaseRng::seed_from_u64(49);
+        for _ in 0..200 {
+            let dim = Dim::Rect(rng.gen_range(1, 9), rng.gen_range(1, 9));
+            let capacity = rng.gen_range(1, MAX_CAPACITY + 1);
+            let room = (dim.w() * dim.h()) as u64 * capacity as u64;
+            // Even a board with every cell full gets all of its mines.
+            let num_mines = rng.gen_range(0, room + 1);
+            let board = Board::new_seeded(dim, num_mines, capacity, rng.gen())
+                .expect("board to be created without a hitch.");
+            assert_eq!(board.num_mines(), num_mines);
+            assert_eq!(
+                Board::new_seeded(dim, room + 1, capacity, rng.gen()).unwrap_err(),
+                BoardError::TooManyMines(room + 1, room),
+            );
+        }
+    }
+
+    #[test]
+    fn numbers_test() {
+        let mut rng = BaseRng::seed_from_u64(49);
+        for _ in 0..200 {
+            let (w, h) = (rng.gen_range(1, 13), rng.gen_range(1, 13));
+            let capacity = rng.gen_range(1, MAX_CAPACITY + 1);
+            // Whatever numbers the cells start with are wrong, and have to be replaced.
+            let cells = (0..h)
+                .map(|_| (0..w)
+                    .map(|_| Cell {
+                        category: match rng.gen_range(0, 4) {
+                            0 => CellCategory::Mine(rng.gen_range(1, capacity + 1)),
+                            1 => CellCategory::Empty(None),
+                            _ => CellCategory::Empty(Some(rng.gen_range(0, 10))),
+                        },
+                        ..Cell::default()
+                    })
+                    .collect::<Vec<_>>()
+                    .into_boxed_slice())
+                .collect::<Vec<_>>()
+                .into_boxed_slice();
+            let mines: Vec<_> = cells.iter()
+                .map(|row| row.iter().map(|cell| cell.is_mine()).collect::<Vec<_>>())
+                .collect();
+            let board = Board::from_cells(cells, capacity).expect("board to be created without a hitch.");
+            for loc @ (x, y) in board.all_locs() {
+                let category = board.cells[y][x].category;
+                assert_eq!(matches!(category, CellCategory::Mine(_)), mines[y][x]);
+                if let CellCategory::Empty(n) = category {
+                    let count = count_around(&board, loc);
+                    assert_eq!(n, Some(count).filter(|n| *n != 0), "{:?}\n{}", loc, board.to_save());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dig_region_test() {
+        let mut rng = BaseRng::seed_from_u64(49);
+        for _ in 0..300 {
+            let mut board = random_board(&mut rng);
+            for loc @ (x, y) in board.all_locs().collect::<Vec<_>>() {
+                if rng.gen_range(0, 10) == 0 {
+                    board.cells[y][x].state = CellState::Marked(rng.gen_range(1, board.capacity() + 1));
+                }
+                assert_eq!(board.surroundings_of(loc).collect::<Vec<_>>().len(), around(&board, loc).len());
+            }
+            let openings: Vec<_> = board.all_locs()
+                .filter(|(x, y)| board.cells[*y][*x] == Cell::default())
+                .collect();
+            if openings.is_empty() {
+                continue;
+            }
+            let start = openings[rng.gen_range(0, openings.len())];
+
+            // Flood through unflagged openings, then take in everything unflagged next to them.
+            let mut opened = vec![start];
+            let mut scanning_locs = vec![start];
+            while let Some(loc) = scanning_locs.pop() {
+                for (x, y) in around(&board, loc) {
+                    if board.cells[y][x] == Cell::default() && !opened.contains(&(x, y)) {
+                        opened.push((x, y));
+                        scanning_locs.push((x, y));
+                    }
+                }
+            }
+            let revealed: Vec<_> = opened.iter()
+                .flat_map(|loc| around(&board, *loc))
+                .filter(|(x, y)| !board.cells[*y][*x].is_marked())
+                .chain(opened.iter().cloned())
+                .collect();
+
+            let before = board.clone();
+            board.dig(start).expect("location to be safe.");
+            let message = format!("{:?}\n{}", start, before.to_save());
+            for (x, y) in board.all_locs() {
+                let (old, new) = (before.cells[y][x], board.cells[y][x]);
+                assert_eq!(new.category, old.category, "{}", message);
+                assert!(!new.is_exploded(), "{}", message);
+                if revealed.contains(&(x, y)) {
+                    assert_eq!(new.state, CellState::Visible, "{:?} {}", (x, y), message);
+                } else {
+                    assert_eq!(new.state, old.state, "{:?} {}", (x, y), message);
+                }
+            }
+
+            // Every cell that changed is kept track of, and nothing else, so the dig can be undone.
+            let changes = board.take_changes();
+            for ((x, y), state) in &changes {
+                assert_eq!(before.cells[*y][*x].state, *state, "{}", message);
+            }
+            board.revert(&changes);
+            for (x, y) in board.all_locs() {
+                assert_eq!(board.cells[y][x], before.cells[y][x], "{:?} {}", (x, y), message);
+            }
+        }
+    }
+
+    #[test]
+    fn chord_test() {
+        let mut rng = BaseRng::seed_from_u64(49);
+        for _ in 0..300 {
+            let mut board = random_board(&mut rng);
+            let capacity = board.capacity();
+            for (x, y) in board.all_locs().collect::<Vec<_>>() {
+                let cell = &mut board.cells[y][x];
+                cell.state = match rng.gen_range(0, 6) {
+                    0 => CellState::Marked(rng.gen_range(1, capacity + 1)),
+                    1 | 2 if !cell.is_mine() => CellState::Visible,
+                    _ => CellState::Hidden,
+                };
+            }
+            let numbers: Vec<_> = board.all_locs()
+                .filter(|(x, y)| {
+                    let cell = board.cells[*y][*x];
+                    cell.state == CellState::Visible && cell.category != CellCategory::Empty(None)
+                })
+                .collect();
+            if numbers.is_empty() {
+                continue;
+            }
+            let loc @ (x, y) = numbers[rng.gen_range(0, numbers.len())];
+            let target = match board.cells[y][x].category {
+                CellCategory::Empty(Some(n)) => n,
+                _ => unreachable!(),
+            };
+            let flags = around(&board, loc).into_iter()
+                .map(|(x, y)| board.cells[y][x].known_mines())
+                .sum::<u8>();
+
+            let before = board.clone();
+            let result = board.chord_at(loc);
+            let message = format!("{:?}\n{}", loc, before.to_save());
+            if flags != target {
+                assert_eq!(result, Ok(()), "{}", message);
+                for (x, y) in board.all_locs() {
+                    assert_eq!(board.cells[y][x].state, before.cells[y][x].state, "{}", message);
+                }
+                continue;
+            }
+            let unflagged: Vec<_> = around(&board, loc).into_iter()
+                .filter(|(x, y)| !before.cells[*y][*x].is_marked())
+                .collect();
+            for (x, y) in &unflagged {
+                assert_eq!(board.cells[*y][*x].state, CellState::Visible, "{}", message);
+            }
+            let hit = unflagged.iter().filter(|(x, y)| before.cells[*y][*x].is_mine()).count() as u32;
+            assert_eq!(result, if hit == 0 { Ok(()) } else { Err(Error::Dead(hit)) }, "{}", message);
+        }
     }
 }
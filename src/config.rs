@@ -0,0 +1,115 @@
+//! Settings that are read from a file at startup, rather than passed on the command line.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use serde::Deserialize;
+
+use crate::controls::{BindingError, Bindings};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Binding(BindingError),
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl From<BindingError> for ConfigError {
+    fn from(e: BindingError) -> Self {
+        Self::Binding(e)
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Parse(e) => write!(f, "Config file is malformed: {}", e),
+            Self::Binding(e) => e.fmt(f),
+        }
+    }
+}
+
+// The file as it is written. Both tables map an action name to the list of inputs that trigger
+// it, e.g. `mark = ["m", "space"]` under `[keys]`, or `dig = ["left"]` under `[mouse]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    keys: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    mouse: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    pub bindings: Bindings,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("minesweeper").join("config.toml"))
+    }
+
+    // A missing file leaves everything at its default.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let file: ConfigFile = toml::from_str(text)?;
+        Ok(Self {
+            bindings: Bindings::with_overrides(&file.keys, &file.mouse)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use termion::event::{Key, MouseButton};
+
+    use crate::controls::{Action, BindingError};
+    use super::{Config, ConfigError};
+
+    #[test]
+    fn parse_test() {
+        let config = Config::parse("\
+[keys]
+dig = [\"space\"]
+mark = [\"f\"]
+flag_chord = []
+
+[mouse]
+mark = [\"right\", \"middle\"]
+chord = []
+").expect("config to parse.");
+        assert_eq!(config.bindings.key(Key::Char(' ')), Some(Action::Dig));
+        assert_eq!(config.bindings.key(Key::Char('u')), None);
+        assert_eq!(config.bindings.button(MouseButton::Middle), Some(Action::Mark));
+
+        match Config::parse("[keys]\nquit = [\"m\"]\n") {
+            Err(ConfigError::Binding(BindingError::Conflict(..))) => (),
+            res => panic!("expected a conflict, got {:?}", res),
+        }
+        assert!(Config::parse("[colors]\n").is_err());
+    }
+}
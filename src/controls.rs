@@ -0,0 +1,457 @@
+//! Everything the player can do, and the keys and mouse buttons that do it.
+
+use std::collections::{BTreeMap, HashMap};
+use termion::event::{Key, MouseButton};
+
+use crate::board::{Board, CellState};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Direction {
+    Up,
+    Left,
+    Down,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Action {
+    Mark,
+    Dig,
+    // Reveals the neighbours of a number once all of its mines are flagged.
+    Chord,
+    // Flags the neighbours of a number once they can only all be mines.
+    FlagChord,
+    LaunchProbe,
+    ShowStats,
+    ShowHelp,
+    Pause,
+    ExitGame,
+    Move(Direction),
+    // Moves by a screenful.
+    Page(Direction),
+    // Moves to the edge of the board.
+    Edge(Direction),
+    JumpTo((usize, usize)),
+    // Moves to the location, and centers the screen on it.
+    ViewAt((usize, usize)),
+    ToggleMinimap,
+}
+
+// Every action that can be bound, in the order they're listed in the help screen, along with its
+// name in the config file and a description.
+const BINDABLE: [(&str, Action, &str); 20] = [
+    ("move_up", Action::Move(Direction::Up), "move up"),
+    ("move_left", Action::Move(Direction::Left), "move left"),
+    ("move_down", Action::Move(Direction::Down), "move down"),
+    ("move_right", Action::Move(Direction::Right), "move right"),
+    ("page_up", Action::Page(Direction::Up), "move up by a screenful"),
+    ("page_down", Action::Page(Direction::Down), "move down by a screenful"),
+    ("edge_left", Action::Edge(Direction::Left), "move to the left edge"),
+    ("edge_right", Action::Edge(Direction::Right), "move to the right edge"),
+    ("edge_top", Action::Edge(Direction::Up), "move to the top edge"),
+    ("edge_bottom", Action::Edge(Direction::Down), "move to the bottom edge"),
+    ("mark", Action::Mark, "mark (repeatedly, to cycle through flag counts and unsure), or flag chord on a number"),
+    ("dig", Action::Dig, "reveal a hidden tile, or chord on an exposed one"),
+    ("chord", Action::Chord, "chord, revealing around a number once its mines are flagged"),
+    ("flag_chord", Action::FlagChord, "flag chord, flagging around a number once its hidden neighbours must be mines"),
+    ("probe", Action::LaunchProbe, "probe for a move once there are no safe ones left"),
+    ("stats", Action::ShowStats, "show statistics"),
+    ("minimap", Action::ToggleMinimap, "toggle the minimap, and click on it to move around"),
+    ("help", Action::ShowHelp, "show this help"),
+    ("pause", Action::Pause, "pause, to restart, start a new game or save"),
+    ("quit", Action::ExitGame, "quit"),
+];
+
+// Only actions that target a cell make sense for the mouse.
+fn is_clickable(action: Action) -> bool {
+    matches!(action, Action::Mark | Action::Dig | Action::Chord | Action::FlagChord)
+}
+
+fn action_named(name: &str) -> Option<Action> {
+    BINDABLE.iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, action, _)| *action)
+}
+
+fn name_of(action: Action) -> &'static str {
+    BINDABLE.iter()
+        .find(|(_, a, _)| *a == action)
+        .map_or("unknown", |(name, _, _)| *name)
+}
+
+const KEY_NAMES: [(&str, Key); 15] = [
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("page_up", Key::PageUp),
+    ("page_down", Key::PageDown),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("insert", Key::Insert),
+    ("delete", Key::Delete),
+    ("backspace", Key::Backspace),
+    ("esc", Key::Esc),
+    ("space", Key::Char(' ')),
+    ("tab", Key::Char('\t')),
+    ("enter", Key::Char('\n')),
+];
+
+pub fn parse_key(s: &str) -> Option<Key> {
+    if let Some((_, key)) = KEY_NAMES.iter().find(|(name, _)| *name == s) {
+        return Some(*key);
+    }
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(rest) = s.strip_prefix("ctrl-") {
+        return single(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = s.strip_prefix("alt-") {
+        return single(rest).map(Key::Alt);
+    }
+    if let Some(Ok(n)) = s.strip_prefix('f').map(str::parse) {
+        return Some(Key::F(n));
+    }
+    single(s).map(Key::Char)
+}
+
+pub fn key_name(key: Key) -> String {
+    if let Some((name, _)) = KEY_NAMES.iter().find(|(_, k)| *k == key) {
+        return (*name).to_owned();
+    }
+    match key {
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        key => format!("{:?}", key),
+    }
+}
+
+const BUTTON_NAMES: [(&str, MouseButton); 5] = [
+    ("left", MouseButton::Left),
+    ("right", MouseButton::Right),
+    ("middle", MouseButton::Middle),
+    ("wheel_up", MouseButton::WheelUp),
+    ("wheel_down", MouseButton::WheelDown),
+];
+
+pub fn parse_button(s: &str) -> Option<MouseButton> {
+    BUTTON_NAMES.iter().find(|(name, _)| *name == s).map(|(_, button)| *button)
+}
+
+pub fn button_name(button: MouseButton) -> &'static str {
+    BUTTON_NAMES.iter().find(|(_, b)| *b == button).map_or("unknown", |(name, _)| *name)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BindingError {
+    UnknownAction(String),
+    UnknownKey(String),
+    UnknownButton(String),
+    NotClickable(String),
+    // The input, followed by the two actions it was bound to.
+    Conflict(String, &'static str, &'static str),
+}
+
+impl std::fmt::Display for BindingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownAction(a) => write!(f, "There is no action named `{}`.", a),
+            Self::UnknownKey(k) => write!(f, "There is no key named `{}`.", k),
+            Self::UnknownButton(b) => write!(f, "There is no mouse button named `{}`.", b),
+            Self::NotClickable(a) => write!(f, "`{}` can't be bound to a mouse button.", a),
+            Self::Conflict(input, a0, a1) => write!(f, "`{}` is bound to both `{}` and `{}`.", input, a0, a1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: HashMap<Key, Action>,
+    buttons: HashMap<MouseButton, Action>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            (Action::Move(Direction::Up), &["up", "w", "k"][..]),
+            (Action::Move(Direction::Left), &["left", "a", "h"][..]),
+            (Action::Move(Direction::Down), &["down", "s", "j"][..]),
+            (Action::Move(Direction::Right), &["right", "d", "l"][..]),
+            (Action::Page(Direction::Up), &["page_up"][..]),
+            (Action::Page(Direction::Down), &["page_down"][..]),
+            (Action::Edge(Direction::Left), &["home"][..]),
+            (Action::Edge(Direction::Right), &["end"][..]),
+            (Action::Edge(Direction::Up), &["g"][..]),
+            (Action::Edge(Direction::Down), &["G"][..]),
+            (Action::Mark, &["m"][..]),
+            (Action::Dig, &["u"][..]),
+            (Action::FlagChord, &["f"][..]),
+            (Action::LaunchProbe, &["!"][..]),
+            (Action::ShowStats, &["i"][..]),
+            (Action::ToggleMinimap, &["v"][..]),
+            (Action::ShowHelp, &["?"][..]),
+            (Action::Pause, &["p", "esc"][..]),
+            (Action::ExitGame, &["q"][..]),
+        ];
+        Self {
+            keys: keys.iter()
+                .flat_map(|(action, keys)| keys.iter().map(move |k| {
+                    (parse_key(k).expect("default keys to be valid."), *action)
+                }))
+                .collect(),
+            buttons: vec![
+                (MouseButton::Left, Action::Dig),
+                (MouseButton::Right, Action::Mark),
+                (MouseButton::Middle, Action::Chord),
+            ].into_iter().collect(),
+        }
+    }
+}
+
+impl Bindings {
+    // Any action that is listed replaces all of its default bindings. Everything else is kept as
+    // is, so long as it doesn't conflict with a new binding.
+    pub fn with_overrides(
+        keys: &BTreeMap<String, Vec<String>>,
+        buttons: &BTreeMap<String, Vec<String>>,
+    ) -> Result<Self, BindingError> {
+        let mut bindings = Self::default();
+
+        let mut new_keys = vec![];
+        for (name, inputs) in keys {
+            let action = action_named(name).ok_or_else(|| BindingError::UnknownAction(name.clone()))?;
+            bindings.keys.retain(|_, a| *a != action);
+            for input in inputs {
+                let key = parse_key(input).ok_or_else(|| BindingError::UnknownKey(input.clone()))?;
+                new_keys.push((key, action));
+            }
+        }
+        for (key, action) in new_keys {
+            if let Some(existing) = bindings.keys.insert(key, action) {
+                if existing != action {
+                    return Err(BindingError::Conflict(key_name(key), name_of(existing), name_of(action)));
+                }
+            }
+        }
+
+        let mut new_buttons = vec![];
+        for (name, inputs) in buttons {
+            let action = action_named(name).ok_or_else(|| BindingError::UnknownAction(name.clone()))?;
+            if !is_clickable(action) {
+                return Err(BindingError::NotClickable(name.clone()));
+            }
+            bindings.buttons.retain(|_, a| *a != action);
+            for input in inputs {
+                let button = parse_button(input).ok_or_else(|| BindingError::UnknownButton(input.clone()))?;
+                new_buttons.push((button, action));
+            }
+        }
+        for (button, action) in new_buttons {
+            if let Some(existing) = bindings.buttons.insert(button, action) {
+                if existing != action {
+                    return Err(BindingError::Conflict(
+                        button_name(button).to_owned(),
+                        name_of(existing),
+                        name_of(action),
+                    ));
+                }
+            }
+        }
+
+        Ok(bindings)
+    }
+
+    pub fn key(&self, key: Key) -> Option<Action> {
+        self.keys.get(&key).cloned()
+    }
+
+    pub fn button(&self, button: MouseButton) -> Option<Action> {
+        self.buttons.get(&button).cloned()
+    }
+
+    // One line per bound action, listing everything that triggers it.
+    pub fn help(&self) -> Vec<String> {
+        BINDABLE.iter()
+            .filter_map(|(_, action, description)| {
+                let mut inputs: Vec<_> = self.keys.iter()
+                    .filter(|(_, a)| *a == action)
+                    .map(|(k, _)| key_name(*k))
+                    .collect();
+                // Sort for a stable order, with special keys before letters.
+                inputs.sort_by_key(|name| (name.chars().count() == 1, name.clone()));
+                inputs.extend(
+                    BUTTON_NAMES.iter()
+                        .filter(|(_, b)| self.buttons.get(b) == Some(action))
+                        .map(|(name, _)| format!("{} click", name.replace('_', " "))),
+                );
+                if *action == Action::Chord {
+                    inputs.push("left+right click".to_owned());
+                }
+                if inputs.is_empty() {
+                    None
+                } else {
+                    Some(format!("{} to {}", inputs.join("/"), description))
+                }
+            })
+            .collect()
+    }
+}
+
+// Mouse buttons that are held down over a cell. Nothing happens until they're released, and only if
+// the pointer is still over the same cell by then, so that a press can be cancelled by dragging
+// off.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Press {
+    buttons: Vec<MouseButton>,
+    // Where the first button went down, on screen.
+    screen: (usize, usize),
+    armed: bool,
+}
+
+impl Press {
+    pub fn new(button: MouseButton, screen: (usize, usize)) -> Self {
+        Self {
+            buttons: vec![button],
+            screen,
+            armed: true,
+        }
+    }
+
+    pub fn screen(&self) -> (usize, usize) {
+        self.screen
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    pub fn add(&mut self, button: MouseButton) {
+        if !self.buttons.contains(&button) {
+            self.buttons.push(button);
+        }
+    }
+
+    pub fn move_to(&mut self, screen: (usize, usize)) {
+        self.armed = screen == self.screen;
+    }
+
+    // Left and right together always chord, whatever they're bound to on their own.
+    pub fn action(&self, bindings: &Bindings) -> Option<Action> {
+        if self.buttons.contains(&MouseButton::Left) && self.buttons.contains(&MouseButton::Right) {
+            Some(Action::Chord)
+        } else {
+            bindings.button(self.buttons[0])
+        }
+    }
+
+    // The hidden cells that would be revealed if the press was released at `loc`, which are drawn
+    // pushed in while the buttons are held.
+    pub fn preview(&self, bindings: &Bindings, board: &Board, loc: (usize, usize)) -> Vec<(usize, usize)> {
+        if !self.armed || !board.is_loc(loc) {
+            return vec![];
+        }
+        let is_hidden = |(x, y): &(usize, usize)| {
+            let state = board.cells[*y][*x].state;
+            state == CellState::Hidden || state == CellState::Unsure
+        };
+        let chording = match self.action(bindings) {
+            Some(Action::Chord) => true,
+            Some(Action::Dig) => !is_hidden(&loc),
+            _ => return vec![],
+        };
+        let mut cells = vec![loc];
+        if chording {
+            cells.extend(board.surroundings_of(loc));
+        }
+        cells.retain(is_hidden);
+        cells
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use termion::event::{Key, MouseButton};
+
+    use crate::board::Board;
+    use super::{parse_key, key_name, Action, BindingError, Bindings, Direction, Press};
+
+    fn overrides(pairs: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        pairs.iter()
+            .map(|(name, inputs)| (name.to_string(), inputs.iter().map(|i| i.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn key_names_test() {
+        for name in &["up", "page_down", "space", "ctrl-c", "alt-x", "f5", "q", "?"] {
+            let key = parse_key(name).expect("key name to be valid.");
+            assert_eq!(&key_name(key), name);
+        }
+        assert_eq!(parse_key("ctrl-"), None);
+        assert_eq!(parse_key("qq"), None);
+    }
+
+    #[test]
+    fn override_test() {
+        let bindings = Bindings::with_overrides(
+            &overrides(&[("mark", &["f", "space"]), ("move_right", &["right", "l"]), ("flag_chord", &[])]),
+            &overrides(&[("mark", &["middle"]), ("chord", &[])]),
+        ).expect("bindings to be valid.");
+        assert_eq!(bindings.key(Key::Char('f')), Some(Action::Mark));
+        assert_eq!(bindings.key(Key::Char('m')), None);
+        assert_eq!(bindings.key(Key::Char('d')), None);
+        assert_eq!(bindings.key(Key::Char('l')), Some(Action::Move(Direction::Right)));
+        assert_eq!(bindings.button(MouseButton::Middle), Some(Action::Mark));
+        assert_eq!(bindings.button(MouseButton::Right), None);
+        assert!(bindings.help().contains(&"space/f/middle click to mark (repeatedly, to cycle through flag counts and unsure), or flag chord on a number".to_owned()));
+    }
+
+    #[test]
+    fn conflict_test() {
+        let err = Bindings::with_overrides(&overrides(&[("dig", &["d"])]), &BTreeMap::new());
+        assert_eq!(err.unwrap_err(), BindingError::Conflict("d".to_owned(), "move_right", "dig"));
+
+        // Middle click chords unless chording is listed too.
+        let err = Bindings::with_overrides(&BTreeMap::new(), &overrides(&[("mark", &["middle"])]));
+        assert_eq!(err.unwrap_err(), BindingError::Conflict("middle".to_owned(), "chord", "mark"));
+
+        let err = Bindings::with_overrides(&BTreeMap::new(), &overrides(&[("quit", &["left"])]));
+        assert_eq!(err.unwrap_err(), BindingError::NotClickable("quit".to_owned()));
+
+        let err = Bindings::with_overrides(&overrides(&[("fly", &["f"])]), &BTreeMap::new());
+        assert_eq!(err.unwrap_err(), BindingError::UnknownAction("fly".to_owned()));
+    }
+
+    #[test]
+    fn press_test() {
+        let bindings = Bindings::default();
+        let board = Board::from_save(b"HHH\nH1H\nHHx\n").expect("board to parse correctly.");
+
+        let mut press = Press::new(MouseButton::Left, (0, 0));
+        assert_eq!(press.action(&bindings), Some(Action::Dig));
+        assert_eq!(press.preview(&bindings, &board, (0, 0)), vec![(0, 0)]);
+        // Digging a number chords on it.
+        assert_eq!(press.preview(&bindings, &board, (1, 1)).len(), 8);
+
+        press.add(MouseButton::Right);
+        assert_eq!(press.action(&bindings), Some(Action::Chord));
+        assert_eq!(press.preview(&bindings, &board, (0, 0)), vec![(0, 0), (1, 0), (0, 1)]);
+
+        press.move_to((1, 0));
+        assert!(!press.is_armed());
+        assert_eq!(press.preview(&bindings, &board, (0, 0)), vec![]);
+        press.move_to((0, 0));
+        assert!(press.is_armed());
+
+        let press = Press::new(MouseButton::Right, (0, 0));
+        assert_eq!(press.preview(&bindings, &board, (0, 0)), vec![]);
+    }
+}
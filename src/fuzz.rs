@@ -0,0 +1,176 @@
+//! Fuzz targets for everything that reads outside input: saved boards, save files, board
+//! definitions, and the commands typed in accessible mode. Like cargo-fuzz targets, each takes
+//! arbitrary bytes and only fails by panicking. The crate is a binary, so instead of a separate
+//! fuzz crate they're driven by a small mutator as tests, starting from the seeds under
+//! `testing/fuzz`. Set `FUZZ_RUNS` (and optionally `FUZZ_SEED`) for a longer run, e.g.
+//! `FUZZ_RUNS=100000 cargo test fuzz`. Debug builds are best, since they also catch overflow.
+//! Inputs that ever caused a panic belong in the seeds, so they keep being checked.
+
+use std::{
+    env,
+    fs,
+    panic,
+    path::{Path, PathBuf},
+};
+use rand::{Rng, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{
+    accessible::Accessible,
+    board::{Board, Dim, MAX_CAPACITY},
+    game::Session,
+    opts::Def,
+    save,
+};
+
+// Mutated inputs never grow past this, so that runs stay quick.
+const MAX_LEN: usize = 4096;
+
+// Bytes and words that mean something to at least one of the parsers.
+const DICTIONARY: [&[u8]; 16] = [
+    b"x", b"H", b"F", b"f", b"!", b"\n", b"\r\n", b",", b" ", b"0",
+    b"18446744073709551616", b"9223372036854775808", b"beginner", b"go", b"capacity = ", b"[[0, 0, 9]]",
+];
+
+pub fn saved_board(data: &[u8]) {
+    let board = match Board::from_save(data) {
+        Ok(board) => board,
+        Err(_) => return,
+    };
+    let save = board.to_save();
+    let again = Board::from_save(save.as_bytes()).expect("saved board to parse again.");
+    assert_eq!(again.to_save(), save);
+}
+
+pub fn save_file(data: &[u8]) {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    if let Ok((_, session)) = save::read(text) {
+        session.remaining_mines();
+        Board::from_save(session.board.to_save().as_bytes()).expect("loaded board to save.");
+    }
+}
+
+pub fn def(data: &[u8]) {
+    let def: Def = match std::str::from_utf8(data).ok().and_then(|text| text.parse().ok()) {
+        Some(def) => def,
+        None => return,
+    };
+    let key = def.key();
+    let again: Def = key.parse().expect("key to parse.");
+    assert_eq!(again.key(), key);
+
+    // Huge boards are fine to ask for, but too much to make over and over.
+    let (dim, mines) = def.dim_and_mines();
+    if dim.w() * dim.h() > MAX_LEN {
+        return;
+    }
+    for capacity in 0..=MAX_CAPACITY + 1 {
+        let fits = (1..=MAX_CAPACITY).contains(&capacity) && mines <= (dim.w() * dim.h()) as u64 * capacity as u64;
+        let board = Board::new_seeded(dim, mines, capacity, [50; 32]);
+        assert_eq!(board.is_ok(), fits);
+    }
+}
+
+pub fn commands(data: &[u8]) {
+    let board = Board::new_seeded(Dim::Square(9), 10, 1, [50; 32]).expect("board to be created without a hitch.");
+    let mut session = Session::new(board, 3);
+    let (mut output, mut stats) = (vec![], None);
+    // Input that isn't text stops the game, which is fine.
+    let _ = Accessible { output: &mut output, stats: &mut stats, save_path: &None }
+        .play(data, &"9,10".parse().expect("def to parse."), &mut session);
+}
+
+fn seeds(name: &str) -> Vec<Vec<u8>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testing").join("fuzz").join(name);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("seed directory to be readable.")
+        .map(|entry| entry.expect("seed directory to be readable.").path())
+        .collect();
+    paths.sort();
+    paths.into_iter()
+        .map(|path| fs::read(path).expect("seed to be readable."))
+        .collect()
+}
+
+fn mutate(seeds: &[Vec<u8>], rng: &mut Xoshiro256PlusPlus) -> Vec<u8> {
+    let mut data = seeds[rng.gen_range(0, seeds.len())].clone();
+    for _ in 0..rng.gen_range(1, 5) {
+        let at = rng.gen_range(0, data.len() + 1);
+        match rng.gen_range(0, 6) {
+            0 if at < data.len() => data[at] ^= 1 << rng.gen_range(0, 8),
+            1 if at < data.len() => data[at] = rng.gen(),
+            2 => {
+                let word = DICTIONARY[rng.gen_range(0, DICTIONARY.len())];
+                data.splice(at..at, word.iter().cloned());
+            },
+            3 => {
+                let end = rng.gen_range(at, data.len() + 1);
+                data.drain(at..end);
+            },
+            4 => {
+                let end = rng.gen_range(at, data.len() + 1);
+                let copy = data[at..end].to_vec();
+                let to = rng.gen_range(0, data.len() + 1);
+                data.splice(to..to, copy);
+            },
+            _ => {
+                let other = &seeds[rng.gen_range(0, seeds.len())];
+                let from = rng.gen_range(0, other.len() + 1);
+                data.truncate(at);
+                data.extend_from_slice(&other[from..]);
+            },
+        }
+    }
+    data.truncate(MAX_LEN);
+    data
+}
+
+fn check(name: &str, target: fn(&[u8]), data: &[u8]) {
+    if let Err(e) = panic::catch_unwind(|| target(data)) {
+        let escaped: String = data.iter().flat_map(|b| std::ascii::escape_default(*b)).map(char::from).collect();
+        eprintln!("{} panicked on \"{}\"", name, escaped);
+        panic::resume_unwind(e);
+    }
+}
+
+// Runs a target over its seeds, then over mutations of them.
+fn fuzz(name: &str, target: fn(&[u8])) {
+    let var = |key| env::var(key).ok().and_then(|value| value.parse().ok());
+    let runs = var("FUZZ_RUNS").unwrap_or(2000);
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(var("FUZZ_SEED").unwrap_or(50));
+    let seeds = seeds(name);
+    for data in &seeds {
+        check(name, target, data);
+    }
+    for _ in 0..runs {
+        check(name, target, &mutate(&seeds, &mut rng));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::fuzz;
+
+    #[test]
+    fn saved_board_test() {
+        fuzz("board", super::saved_board);
+    }
+
+    #[test]
+    fn save_file_test() {
+        fuzz("save", super::save_file);
+    }
+
+    #[test]
+    fn def_test() {
+        fuzz("def", super::def);
+    }
+
+    #[test]
+    fn commands_test() {
+        fuzz("commands", super::commands);
+    }
+}
@@ -0,0 +1,507 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    board::{Board, BoardError, CellState, Error},
+    opts::{Def, Opts},
+    solver::{self, Budget, IncrementalSolver, Solver},
+};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum State {
+    Playing,
+    Won,
+    Lost,
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Playing => "Playing",
+            Self::Won => "Won",
+            Self::Lost => "Lost",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// A look back at the move that lost the game.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Analysis {
+    pub fatal: (usize, usize),
+    // A cell that was known to be safe at the time, if there were any.
+    pub safe_move: Option<(usize, usize)>,
+    // The chance that the cell held a mine, given everything that was revealed at the time. Missing
+    // if the board was too complicated to work out, or a flag was wrong.
+    pub probability: Option<f64>,
+}
+
+impl std::fmt::Display for Analysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (x, y) = self.fatal;
+        match self.probability {
+            Some(p) => write!(f, "({}, {}) had a {:.1}% chance of being a mine. ", x, y, 100.0 * p)?,
+            None => write!(f, "The chance of ({}, {}) being a mine could not be worked out. ", x, y)?,
+        }
+        match (self.safe_move, self.probability) {
+            (_, Some(p)) if p >= 1.0 => write!(f, "It was certainly a mine, so this was a mistake."),
+            (Some((sx, sy)), _) => write!(f, "({}, {}) was known to be safe, so this was a mistake.", sx, sy),
+            (None, _) => write!(f, "There were no safe moves left, so this was a forced guess."),
+        }
+    }
+}
+
+// Everything needed to start a new game, other than the board definition.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Settings {
+    pub capacity: u8,
+    pub unsure_marks: bool,
+    pub lives: u32,
+    pub auto_flag: bool,
+    pub budget: Budget,
+}
+
+impl Settings {
+    pub fn from_opts(opts: &Opts) -> Self {
+        Self {
+            capacity: opts.capacity,
+            unsure_marks: !opts.no_unsure,
+            lives: opts.lives,
+            auto_flag: opts.auto_flag,
+            budget: opts.budget(),
+        }
+    }
+
+    // Fails if there's no room for the mines at this capacity, or the capacity itself is out of range.
+    pub fn new_session(&self, def: &Def) -> Result<Session, BoardError> {
+        let (dim, mines) = def.dim_and_mines();
+        let mut board = Board::new(dim, mines, self.capacity)?;
+        board.set_unsure_marks(self.unsure_marks);
+        let mut session = Session::new(board, self.lives);
+        session.set_auto_flag(self.auto_flag);
+        session.set_budget(self.budget);
+        Ok(session)
+    }
+}
+
+// A single game, played out on a board. Anything that isn't a rule of the board itself, such as
+// how many times the player is allowed to hit a mine, is tracked here.
+#[derive(Debug)]
+pub struct Session {
+    pub board: Board,
+    initial_lives: u32,
+    lives: u32,
+    state: State,
+    clicks: u64,
+    probes: u64,
+    // Flags cells that the solver can prove are mines after every move.
+    auto_flag: bool,
+    // Moves are ignored, and the clock is stopped, while paused.
+    paused: bool,
+    // The move that lost the game, and the board from just before it.
+    fatal: Option<((usize, usize), Board)>,
+    // Time on the clock from before it was last started.
+    banked: Duration,
+    // The clock starts on the first move, and stops once the game is over.
+    running_since: Option<Instant>,
+    // Kept up to date with the board after every move.
+    solver: IncrementalSolver,
+    // How much work the solver may do on every move.
+    budget: Budget,
+}
+
+impl Session {
+    pub fn new(mut board: Board, lives: u32) -> Self {
+        // The solver starts from the board as it is, so anything changed before now is caught up on
+        // already.
+        board.take_changes();
+        Self {
+            solver: IncrementalSolver::new(&board),
+            budget: Budget::unlimited(),
+            board,
+            initial_lives: lives,
+            lives,
+            state: State::Playing,
+            clicks: 0,
+            probes: 0,
+            auto_flag: false,
+            paused: false,
+            fatal: None,
+            banked: Duration::from_secs(0),
+            running_since: None,
+        }
+    }
+
+    // Picks up a game where it was left off. The clock starts again with the next move.
+    pub fn restore(board: Board, initial_lives: u32, lives: u32, clicks: u64, probes: u64, elapsed: Duration) -> Self {
+        Self {
+            lives,
+            clicks,
+            probes,
+            banked: elapsed,
+            ..Self::new(board, initial_lives)
+        }
+    }
+
+    // The same board, with everything hidden again.
+    pub fn restart(&self) -> Self {
+        let mut board = self.board.clone();
+        board.reset();
+        let mut session = Self::new(board, self.initial_lives);
+        session.auto_flag = self.auto_flag;
+        session.set_budget(self.budget);
+        session
+    }
+
+    pub fn set_auto_flag(&mut self, auto_flag: bool) {
+        self.auto_flag = auto_flag;
+    }
+
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+        self.solver = IncrementalSolver::with_budget(&self.board, budget);
+    }
+
+    // Whether the player had help from the solver.
+    pub fn is_assisted(&self) -> bool {
+        self.auto_flag
+    }
+
+    pub fn initial_lives(&self) -> u32 {
+        self.initial_lives
+    }
+
+    pub fn lives(&self) -> u32 {
+        self.lives
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn clicks(&self) -> u64 {
+        self.clicks
+    }
+
+    pub fn probes(&self) -> u64 {
+        self.probes
+    }
+
+    // Can go negative if the player places too many flags.
+    pub fn remaining_mines(&self) -> i64 {
+        self.board.num_mines() as i64 - self.board.num_known_mines() as i64
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.banked + self.running_since.map_or(Duration::from_secs(0), |since| since.elapsed())
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == State::Playing {
+            self.paused = true;
+            self.stop_clock();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.paused = false;
+            if self.clicks != 0 {
+                self.running_since = Some(Instant::now());
+            }
+        }
+    }
+
+    // The mines that went off in the move that lost the game.
+    pub fn fatal_cells(&self) -> Vec<(usize, usize)> {
+        match &self.fatal {
+            Some((_, before)) => self.board.all_locs()
+                .filter(|(x, y)| self.board.cells[*y][*x].is_exploded() && !before.cells[*y][*x].is_exploded())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn analyze(&self) -> Option<Analysis> {
+        let (fatal, before) = self.fatal.as_ref()?;
+        let safe_move = match Solver::new(before).calculate_known_cells() {
+            Ok(Some(known)) => known.empty.into_iter().next(),
+            _ => None,
+        };
+        let probability = solver::mine_probabilities(before).and_then(|probabilities| {
+            // Chording is judged by the riskiest cell it opened.
+            let opened: Vec<_> = if before.cells[fatal.1][fatal.0].state == CellState::Visible {
+                before.surroundings_of(*fatal).collect()
+            } else {
+                vec![*fatal]
+            };
+            opened.iter().filter_map(|loc| probabilities.get(loc)).cloned().fold(None, |max: Option<f64>, p| {
+                Some(max.map_or(p, |max| max.max(p)))
+            })
+        });
+        Some(Analysis {
+            fatal: *fatal,
+            safe_move,
+            probability,
+        })
+    }
+
+    fn stop_clock(&mut self) {
+        if let Some(since) = self.running_since.take() {
+            self.banked += since.elapsed();
+        }
+    }
+}
+
+// Moves. These defer to the board, but only report death once the player is out of lives.
+impl Session {
+    pub fn mark(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        if !self.start_move() {
+            return Ok(());
+        }
+        let res = self.board.mark(point);
+        self.end_move(res)
+    }
+
+    pub fn dig(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        self.risky_move(point, Board::dig)
+    }
+
+    pub fn chord(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        self.risky_move(point, Board::chord_at)
+    }
+
+    pub fn flag_chord(&mut self, point: (usize, usize)) -> Result<(), Error> {
+        if !self.start_move() {
+            return Ok(());
+        }
+        let res = self.board.flag_chord_at(point);
+        self.end_move(res)
+    }
+
+    pub fn launch_probe(&mut self) -> Result<(), Error> {
+        if !self.start_move() {
+            return Ok(());
+        }
+        self.probes += 1;
+        // Probing while there's still a move that's certain costs a life, and reveals nothing. If a
+        // flag is wrong, the board can't be reasoned about, and if the solver ran out of budget, it
+        // wasn't reasoned about fully, so either way the player gets the benefit of the doubt.
+        let res = match self.solver.known_cells() {
+            Ok(Some(_)) if self.solver.is_complete() => Err(Error::Dead(1)),
+            Ok(_) | Err(()) => Ok(()),
+        };
+        self.end_move(res)
+    }
+
+    // For moves that can lose the game. The board from before the move is kept if it does, so that
+    // the move can be looked back on.
+    fn risky_move(
+        &mut self,
+        point: (usize, usize),
+        f: impl FnOnce(&mut Board, (usize, usize)) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if !self.start_move() {
+            return Ok(());
+        }
+        let res = f(&mut self.board, point);
+        // Only the cells that the move changed are kept, and the board from before it is only
+        // pieced back together if it lost the game.
+        let changes = self.board.changes().to_vec();
+        let res = self.end_move(res);
+        if self.state == State::Lost {
+            let mut before = self.board.clone();
+            before.revert(&changes);
+            self.fatal = Some((point, before));
+        }
+        res
+    }
+
+    // Returns whether the move should go ahead at all.
+    fn start_move(&mut self) -> bool {
+        if self.state != State::Playing || self.paused {
+            return false;
+        }
+        self.clicks += 1;
+        if self.running_since.is_none() {
+            self.running_since = Some(Instant::now());
+        }
+        true
+    }
+
+    fn end_move(&mut self, res: Result<(), Error>) -> Result<(), Error> {
+        let res = match res {
+            Err(Error::Dead(n)) => {
+                // Digs and chords have already revealed the mines, which now count as flags. Each one
+                // costs a life, even when a single chord sets off several. A bad probe reveals
+                // nothing, and costs a single life.
+                self.lives = self.lives.saturating_sub(n);
+                if self.lives == 0 {
+                    self.state = State::Lost;
+                    Err(Error::Dead(n))
+                } else {
+                    Ok(())
+                }
+            },
+            res => res,
+        };
+        let changes = self.board.take_changes();
+        self.solver.update(&self.board, &changes);
+        if self.state == State::Playing && self.auto_flag {
+            self.flag_known_mines();
+        }
+        if self.state == State::Playing && self.board.is_all_but_mines_revealed() {
+            self.state = State::Won;
+        }
+        if self.state != State::Playing {
+            self.stop_clock();
+        }
+        res
+    }
+
+    fn flag_known_mines(&mut self) {
+        let mines = match self.solver.known_cells() {
+            Ok(Some(known)) => known.mines.clone(),
+            // Either nothing is certain, or a flag is wrong and the board can't be reasoned about.
+            _ => return,
+        };
+        for loc in mines {
+            self.board.flag(loc).expect("solver to only report locations on the board.");
+        }
+        let changes = self.board.take_changes();
+        self.solver.update(&self.board, &changes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        board::{Board, CellState, Error},
+        solver::Budget,
+    };
+
+    use super::{Session, State};
+
+    #[test]
+    fn lives_test() {
+        let board = Board::from_save(b"x1HHH\nx2HHx\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board, 2);
+
+        assert_eq!(session.dig((0, 0)), Ok(()));
+        assert_eq!(session.lives(), 1);
+        assert_eq!(session.board.cells[0][0].state, CellState::Visible);
+
+        // The exploded mine counts as a flag, so this chord only needs the other one.
+        session.mark((0, 1)).expect("location to be on the board.");
+        assert_eq!(session.dig((1, 1)), Ok(()));
+        assert_eq!(session.board.cells[1][2].state, CellState::Visible);
+
+        // Digging an already exploded mine is free.
+        assert_eq!(session.dig((0, 0)), Ok(()));
+        session.mark((0, 1)).expect("location to be on the board.");
+        assert_eq!(session.dig((0, 1)), Err(Error::Dead(1)));
+        assert_eq!(session.lives(), 0);
+        assert_eq!(session.state(), State::Lost);
+        assert_eq!(session.clicks(), 6);
+    }
+
+    #[test]
+    fn chord_lives_test() {
+        // Both flags are wrong, so chording the 2 sets off both mines above it.
+        let board = Board::from_save(b"xHx\nH2H\nHHH\n")
+            .expect("board to parse correctly.");
+        for (lives, left, res) in [(3, 1, Ok(())), (2, 0, Err(Error::Dead(2)))].iter().cloned() {
+            let mut session = Session::new(board.clone(), lives);
+            session.dig((1, 1)).expect("location to be safe.");
+            session.mark((1, 0)).expect("location to be on the board.");
+            session.mark((0, 1)).expect("location to be on the board.");
+            assert_eq!(session.chord((1, 1)), res);
+            assert_eq!(session.lives(), left);
+            assert_eq!(session.board.cells[0][0].state, CellState::Visible);
+            assert_eq!(session.board.cells[0][2].state, CellState::Visible);
+        }
+    }
+
+    #[test]
+    fn auto_flag_test() {
+        let board = Board::from_save(b"x1H\n11H\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board, 1);
+        session.set_auto_flag(true);
+
+        assert_eq!(session.dig((2, 0)), Ok(()));
+        assert_eq!(session.board.cells[0][0].state, CellState::Marked(1));
+        assert!(session.is_assisted());
+    }
+
+    #[test]
+    fn pause_test() {
+        let board = Board::from_save(b"x1H\n11H\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board, 1);
+
+        session.pause();
+        assert_eq!(session.dig((2, 0)), Ok(()));
+        assert_eq!(session.clicks(), 0);
+        assert_eq!(session.elapsed(), Duration::from_secs(0));
+
+        session.resume();
+        assert_eq!(session.dig((2, 0)), Ok(()));
+        assert_eq!(session.board.cells[0][2].state, CellState::Visible);
+
+        let restarted = session.restart();
+        assert_eq!(restarted.board.cells[0][2].state, CellState::Hidden);
+        assert_eq!(restarted.clicks(), 0);
+    }
+
+    #[test]
+    fn probe_test() {
+        // The mine in the corner is certain, so probing instead of flagging it is a mistake.
+        let board = Board::from_save(b"x1H\n11H\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board.clone(), 1);
+        assert_eq!(session.launch_probe(), Err(Error::Dead(1)));
+        assert_eq!(session.state(), State::Lost);
+
+        // With lives to spare, it only costs one of them.
+        let mut session = Session::new(board.clone(), 2);
+        assert_eq!(session.launch_probe(), Ok(()));
+        assert_eq!(session.lives(), 1);
+        assert_eq!(session.state(), State::Playing);
+        assert_eq!(session.board.to_save(), board.to_save());
+
+        // Unless the solver never got far enough to see it.
+        let mut session = Session::new(board, 1);
+        session.set_budget(Budget {
+            time: None,
+            nodes: Some(0),
+        });
+        assert_eq!(session.launch_probe(), Ok(()));
+        assert_eq!(session.state(), State::Playing);
+    }
+
+    #[test]
+    fn analyze_test() {
+        // Nothing is revealed, so the first click is always a guess.
+        let board = Board::from_save(b"xH\nHH\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board, 1);
+        assert_eq!(session.dig((0, 0)), Err(Error::Dead(1)));
+        let analysis = session.analyze().expect("game to have been lost by digging.");
+        assert_eq!(analysis.safe_move, None);
+        assert_eq!(analysis.probability, Some(0.25));
+        assert!(analysis.to_string().ends_with("so this was a forced guess."));
+
+        // Both 1s can only be explained by the cells next to them.
+        let board = Board::from_save(b"x11x\n")
+            .expect("board to parse correctly.");
+        let mut session = Session::new(board, 1);
+        assert_eq!(session.dig((3, 0)), Err(Error::Dead(1)));
+        let analysis = session.analyze().expect("game to have been lost by digging.");
+        assert_eq!(analysis.fatal, (3, 0));
+        assert_eq!(analysis.probability, Some(1.0));
+        assert!(analysis.to_string().ends_with("so this was a mistake."));
+    }
+}
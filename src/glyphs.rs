@@ -0,0 +1,133 @@
+//! The characters the board is drawn with. Some terminals and serial consoles can't show the
+//! box drawing characters, so there's a plain ASCII set to fall back on.
+
+use std::env;
+
+use crate::board::{Cell, CellCategory, CellState};
+
+#[derive(Debug)]
+pub struct GlyphSetDoesNotExist;
+
+impl std::fmt::Display for GlyphSetDoesNotExist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Requested glyph set does not exist. Pick either ascii or unicode.")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum GlyphSet {
+    Ascii,
+    Unicode,
+}
+
+impl std::str::FromStr for GlyphSet {
+    type Err = GlyphSetDoesNotExist;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Self::Ascii),
+            "unicode" => Ok(Self::Unicode),
+            _ => Err(GlyphSetDoesNotExist),
+        }
+    }
+}
+
+impl GlyphSet {
+    // Goes by the first of the locale variables that is set, the same way the C library does.
+    // Unicode is only used if the locale says the terminal speaks UTF-8.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty());
+        Self::for_locale(locale.as_deref())
+    }
+
+    fn for_locale(locale: Option<&str>) -> Self {
+        match locale {
+            Some(locale) => {
+                let locale = locale.to_lowercase();
+                if locale.contains("utf-8") || locale.contains("utf8") {
+                    Self::Unicode
+                } else {
+                    Self::Ascii
+                }
+            },
+            None => Self::Ascii,
+        }
+    }
+
+    pub fn cell(self, cell: &Cell) -> char {
+        match (self, cell.state) {
+            (Self::Ascii, CellState::Hidden) => '#',
+            (Self::Unicode, CellState::Hidden) => '\u{25A1}',
+            (_, CellState::Unsure) => '?',
+            (_, CellState::Marked(1)) => 'F',
+            // Stacked flags count on through the alphabet, so G is two flags. Lowercase letters
+            // are left for numbers.
+            (Self::Ascii, CellState::Marked(n)) => (b'F' + n - 1) as char,
+            // Circled digits, so that multiple flags can't be confused with a number.
+            (Self::Unicode, CellState::Marked(n)) => std::char::from_u32(0x245F + n as u32)
+                .expect("flag count to be small enough to have a circled digit."),
+            (_, CellState::Visible) => match cell.category {
+                CellCategory::Mine(_) => self.mine(),
+                CellCategory::Empty(None) => match self {
+                    Self::Ascii => '.',
+                    Self::Unicode => '\u{25A0}',
+                },
+                // Counts above 9 can only happen with multiple mines per cell, and continue on
+                // into the alphabet.
+                CellCategory::Empty(Some(n)) => std::char::from_digit(n as u32, 36)
+                    .expect("mine count to be small enough to fit in a single character."),
+            },
+        }
+    }
+
+    pub fn mine(self) -> char {
+        match self {
+            Self::Ascii => '*',
+            Self::Unicode => 'M',
+        }
+    }
+
+    // A flag on a cell without a mine, once the game is lost.
+    pub fn wrong_flag(self) -> char {
+        'X'
+    }
+
+    // From unexplored to fully revealed.
+    pub fn shades(self) -> [char; 5] {
+        match self {
+            Self::Ascii => [' ', '.', ':', '+', '#'],
+            Self::Unicode => [' ', '\u{2591}', '\u{2592}', '\u{2593}', '\u{2588}'],
+        }
+    }
+
+    // Horizontal, vertical, then the corners clockwise from the top left.
+    pub fn frame(self) -> [char; 6] {
+        match self {
+            Self::Ascii => ['-', '|', '+', '+', '+', '+'],
+            Self::Unicode => ['\u{2500}', '\u{2502}', '\u{250C}', '\u{2510}', '\u{2518}', '\u{2514}'],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::board::Board;
+    use super::GlyphSet;
+
+    #[test]
+    fn glyph_test() {
+        assert_eq!(GlyphSet::for_locale(Some("en_US.UTF-8")), GlyphSet::Unicode);
+        assert_eq!(GlyphSet::for_locale(Some("C.utf8")), GlyphSet::Unicode);
+        assert_eq!(GlyphSet::for_locale(Some("C")), GlyphSet::Ascii);
+        assert_eq!(GlyphSet::for_locale(None), GlyphSet::Ascii);
+
+        let board = Board::from_save(b"x2fH\n2!1 \n").expect("board to parse correctly.");
+        let text = |glyphs: GlyphSet| board.cells.iter()
+            .map(|row| row.iter().map(|cell| glyphs.cell(cell)).collect::<String>())
+            .collect::<Vec<_>>();
+        assert_eq!(text(GlyphSet::Ascii), ["#2F#", "2*1."]);
+        assert_eq!(text(GlyphSet::Unicode), ["\u{25A1}2F\u{25A1}", "2M1\u{25A0}"]);
+    }
+}
@@ -1,44 +1,87 @@
-use std::{io::{stdin, stdout, Read, Write}, collections::VecDeque};
+use std::{
+    io::{self, stdin, stdout, Write},
+    collections::VecDeque,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
 use structopt::StructOpt;
 
 use termion::{
-    raw::{IntoRawMode, RawTerminal},
-    input::{TermRead, MouseTerminal, Events},
-    event::{Key, MouseButton, Event, MouseEvent},
+    raw::IntoRawMode,
+    input::{TermRead, MouseTerminal},
+    event::{Event, MouseButton, MouseEvent},
 };
 
 mod util;
+mod parallel;
 
 mod solver;
-use solver::Solver;
 mod board;
-use board::{Board, Dim, Error};
+use board::Error;
+
+// How often the status bar is redrawn while waiting on input.
+const TICK: Duration = Duration::from_millis(250);
+mod game;
+use game::{Settings, State};
 
 mod opts;
-use opts::{Opts, Def, Preset};
+use opts::{Opts, Command};
 
-enum Direction {
-    Up,
-    Left,
-    Down,
-    Right,
-}
+mod stats;
+use stats::{Record, Store};
+
+mod render;
+use render::{Renderer, Theme};
+
+mod glyphs;
+use glyphs::GlyphSet;
+
+mod controls;
+use controls::{Action, Bindings, Direction, Press};
+
+mod config;
+use config::Config;
+
+mod save;
+
+mod menu;
+use menu::{Menu, PauseChoice};
+
+mod accessible;
+use accessible::Accessible;
+
+mod bench;
+
+#[cfg(test)]
+mod fuzz;
 
-enum Action {
-    Mark,
-    Dig,
-    LaunchProbe,
-    ExitGame,
-    Move(Direction),
-    JumpTo((usize, usize)),
-}
 struct Input {
     action: Action,
     point: (usize, usize),
 }
 
-fn read_input<T: Read + TermRead>(stream: &mut Events<T>) -> Result<Option<(Action, Option<Action>)>, ()> {
-    let next_event = stream.next().expect("Terminal read to be fine."); // TODO Convert `expect` to `Error`.
+// Terminal events are read on their own thread, so that the game can keep ticking while waiting on
+// the player.
+fn spawn_event_reader() -> Receiver<io::Result<Event>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for ev in stdin().events() {
+            if tx.send(ev).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// Mouse buttons only act once they're released, so presses are tracked across events in `press`.
+fn read_input(
+    next_event: io::Result<Event>,
+    press: &mut Option<Press>,
+    renderer: &Renderer,
+    bindings: &Bindings,
+) -> Result<Option<(Action, Option<Action>)>, ()> {
     match next_event {
         Err(_) => {
             // Unexpected error. TODO Consider panicking.
@@ -46,151 +89,235 @@ fn read_input<T: Read + TermRead>(stream: &mut Events<T>) -> Result<Option<(Acti
         },
         Ok(ev) => match ev {
             Event::Unsupported(_) => Ok(None),
-            Event::Key(k) => {
-                let action = match k {
-                    Key::Up | Key::Char('w') | Key::Char('k') =>
-                        Some(Action::Move(Direction::Up)),
-                    Key::Left | Key::Char('a') | Key::Char('h') =>
-                        Some(Action::Move(Direction::Left)),
-                    Key::Down | Key::Char('s') | Key::Char('j') =>
-                        Some(Action::Move(Direction::Down)),
-                    Key::Right | Key::Char('d') | Key::Char('l') =>
-                        Some(Action::Move(Direction::Right)),
-                    Key::Char('m') => Some(Action::Mark),
-                    Key::Char('u') => Some(Action::Dig),
-                    Key::Char('q') => Some(Action::ExitGame),
-                    Key::Char('!') => Some(Action::LaunchProbe),
-                    _ => None,
-                };
-                Ok(action.map(|a| (a, None)))
-            },
-            Event::Mouse(m) => match m {
-                MouseEvent::Release(_, _) => Ok(None),
-                MouseEvent::Hold(_, _) => Ok(None),
-                MouseEvent::Press(button, x, y) => {
-                    // TODO Figure out how to handle two buttons down at the same time.
-                    let action = match button {
-                        MouseButton::Left => Action::Dig,
-                        MouseButton::Right => Action::Mark,
-                        _ => return Ok(None),
-                    };
-
-                    for ev in stream {
-                        match ev {
-                            Err(_) => return Ok(None),
-                            // Ignore until we hit a release.
-                            Ok(e) => {
-                                if let Event::Mouse(MouseEvent::Release(x_up, y_up)) = e {
-                                    if (x_up, y_up) == (x, y) {
-                                        // Input events are 1 indexed, so we're converting it to
-                                        // being 0 indexed.
-                                        let coords = (x_up as usize - 1, y_up as usize - 1);
-                                        return Ok(Some(
-                                            (Action::JumpTo(coords), Some(action))
-                                        ));
-                                    } else {
-                                        return Ok(None);
-                                    }
-                                }
-                            }
+            Event::Key(k) => Ok(bindings.key(k).map(|a| (a, None))),
+            Event::Mouse(m) => {
+                // Input events are 1 indexed, so we're converting it to being 0 indexed.
+                let to_screen = |x: u16, y: u16| (x as usize - 1, y as usize - 1);
+                match m {
+                    // The wheel never reports a release, so it acts straight away.
+                    MouseEvent::Press(button @ MouseButton::WheelUp, x, y)
+                    | MouseEvent::Press(button @ MouseButton::WheelDown, x, y) => {
+                        Ok(bindings.button(button).and_then(|action| {
+                            renderer.to_board(to_screen(x, y)).map(|coords| (Action::JumpTo(coords), Some(action)))
+                        }))
+                    },
+                    MouseEvent::Press(button, x, y) => {
+                        match press {
+                            Some(press) => press.add(button),
+                            None => *press = Some(Press::new(button, to_screen(x, y))),
                         }
-                    }
-
-                    // Unexpected end.
-                    Err(())
-                },
-            }
+                        Ok(None)
+                    },
+                    MouseEvent::Hold(x, y) => {
+                        if let Some(press) = press {
+                            press.move_to(to_screen(x, y));
+                        }
+                        Ok(None)
+                    },
+                    // When several buttons were held, the first release acts and the rest are
+                    // dropped here.
+                    MouseEvent::Release(x, y) => {
+                        let mut press = match press.take() {
+                            Some(press) => press,
+                            None => return Ok(None),
+                        };
+                        press.move_to(to_screen(x, y));
+                        if !press.is_armed() {
+                            return Ok(None);
+                        }
+                        // Clicks outside of the board are dropped, and clicks on the minimap only
+                        // move the view.
+                        let screen = press.screen();
+                        if renderer.is_on_minimap(screen) {
+                            return Ok(renderer.to_board(screen).map(|coords| {
+                                (Action::ViewAt(coords), None)
+                            }));
+                        }
+                        Ok(press.action(bindings).and_then(|action| {
+                            renderer.to_board(screen).map(|coords| (Action::JumpTo(coords), Some(action)))
+                        }))
+                    },
+                }
+            },
         },
     }
 }
 
-fn print_board<W: Write>(
-    output: &mut RawTerminal<W>,
-    board: &Board,
-    top_left: (usize, usize),
-    current_point: (usize, usize),
-) -> Option<(usize, usize)> {
-    let mut new_top_left = top_left;
-    let size = termion::terminal_size().expect("no problem getting the terminal size.");
-    let size = (size.0 as usize, size.1 as usize);
-    if size.0 == 0 || size.1 == 0 {
-        return None;
-    }
-    let bot_right = (top_left.0 + size.0, top_left.1 + size.1);
-    if current_point.0 >= bot_right.0 {
-        new_top_left.0 = current_point.0 - size.0;
-    }
-    if current_point.0 < top_left.0 {
-        new_top_left.0 = current_point.0;
-    }
-    if current_point.1 >= bot_right.1 {
-        new_top_left.1 = current_point.1 - size.1;
+fn main() {
+    let cfg = Opts::from_args();
+
+    let mut stats = match cfg.stats_file.clone().or_else(Store::default_path) {
+        Some(path) => match Store::load(&path) {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                eprintln!("Could not load statistics from {}: {}", path.display(), e);
+                None
+            },
+        },
+        None => None,
+    };
+
+    let theme = match Theme::load(&cfg.theme) {
+        Ok(theme) => theme,
+        Err(e) => {
+            eprintln!("Could not load theme {}: {}", cfg.theme, e);
+            return;
+        },
+    };
+    let mut renderer = Renderer::new(theme, cfg.glyphs.unwrap_or_else(GlyphSet::detect));
+
+    let bindings = match cfg.config.clone().or_else(Config::default_path) {
+        Some(path) => match Config::load(&path) {
+            Ok(config) => config.bindings,
+            Err(e) => {
+                eprintln!("Could not load config from {}: {}", path.display(), e);
+                return;
+            },
+        },
+        None => Bindings::default(),
+    };
+
+    if let Some(Command::Bench { def, games, seed, threads, solver }) = &cfg.cmd {
+        let seed = match seed {
+            Some(hex) => match bench::parse_seed(hex) {
+                Some(seed) => seed,
+                None => {
+                    eprintln!("Seeds are 64 hex digits.");
+                    return;
+                },
+            },
+            None => rand::random(),
+        };
+        println!("{}, capacity {}, seed {}, {} solver", def, cfg.capacity, util::to_hex(&seed), solver);
+        match parallel::with_threads(*threads, || bench::run(def, cfg.capacity, *games, seed, *solver, cfg.budget())) {
+            Ok(Ok(report)) => println!("{}", report),
+            Ok(Err(e)) => eprintln!("Could not create a board: {}", e),
+            Err(()) => eprintln!("Could not start the threads to play on."),
+        }
+        return;
     }
-    if current_point.1 < top_left.1 {
-        new_top_left.1 = current_point.1;
+
+    if let Some(Command::Stats) = cfg.cmd {
+        match stats {
+            Some(stats) => for (def, summary) in stats.report(cfg.capacity, cfg.lives, cfg.auto_flag) {
+                println!("{}: {}", def, summary);
+            },
+            None => println!("Statistics are unavailable."),
+        }
+        return;
     }
 
-    let snippet = board.display(size, new_top_left).expect("no problem with updating the screen.");
+    let save_path = cfg.save_file.clone().or_else(save::default_path);
+    let mut settings = Settings::from_opts(&cfg);
 
-    write!(output, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1))
-       .expect("write to be fine.");
-    for row in &snippet[..] {
-        for cell in &row[..] {
-            write!(output, "{}", cell).expect("output to standard out without an issue.");
+    // A board picked on the command line is made straight away, so that a bad one is reported before
+    // the terminal is taken over.
+    let picked = match cfg.def.clone() {
+        Some(def) => match settings.new_session(&def) {
+            Ok(session) => Some((def, session)),
+            Err(e) => {
+                eprintln!("Could not create a board for {}: {}", def, e);
+                return;
+            },
+        },
+        None => None,
+    };
+
+    if cfg.accessible {
+        // There's no menu in accessible mode, so the board is asked for up front if it's missing.
+        let (def, mut session) = match picked {
+            Some(game) => game,
+            None => loop {
+                println!("Board to play on: beginner, intermediate, advanced, `width,mines` or `width,height,mines`.");
+                let mut line = String::new();
+                match stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => match line.trim().parse() {
+                        Ok(def) => match settings.new_session(&def) {
+                            Ok(session) => break (def, session),
+                            Err(e) => println!("Could not create a board for {}: {}", def, e),
+                        },
+                        Err(e) => println!("Could not read `{}`: {}", line.trim(), e),
+                    },
+                }
+            },
+        };
+        let mut accessible = Accessible {
+            output: &mut stdout(),
+            stats: &mut stats,
+            save_path: &save_path,
+        };
+        if let Err(e) = accessible.play(stdin().lock(), &def, &mut session) {
+            eprintln!("Could not play: {}", e);
         }
-        write!(output, "\n\r").expect("write to be fine.");
+        return;
     }
-    write!(
-        output,
-        "{}",
-        termion::cursor::Goto(
-            (current_point.0 + 1) as u16,
-            (current_point.1 + 1) as u16,
-        ),
-    ).expect("write to be fine.");
-    output.flush().expect("flush to be fine.");
-
-    Some(new_top_left)
-}
 
-fn main() {
-    let cfg = Opts::from_args();
-
-    println!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
-    // TODO ASCII art for the welcome message.
-    println!("\
-Hello, and welcome to Minesweeper. (The ASCII art is in the works. I swear.)
-We're working on the control scheme, but for now press:
-\tup/w/k to move up
-\tleft/a/h to move left
-\tdown/s/j to move down
-\tright/d/l to move right
-\tm/right click to mark
-\tu/left click on a hidden tile to reveal
-\tu/left click on an exposed tile to chord
-
-Press any key to continue.");
+    // The menu is skipped if the board was picked on the command line.
+    if picked.is_some() {
+        println!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
+        // TODO ASCII art for the welcome message.
+        println!("Hello, and welcome to Minesweeper. (The ASCII art is in the works. I swear.)");
+        println!("Press:");
+        for line in bindings.help() {
+            println!("\t{}", line);
+        }
+        println!("\nPress any key to continue.");
+    }
 
     let mut stdout = MouseTerminal::from(stdout().into_raw_mode().unwrap());
-    let mut events = stdin().events();
-    while let None = events.next() {}
+    let events = spawn_event_reader();
 
-    let mut board = match cfg.def {
-        Def::Preset(Preset::Beginner) => Board::beginner(),
-        Def::Preset(Preset::Intermediate) => Board::intermediate(),
-        Def::Preset(Preset::Advanced) => Board::advanced(),
-        Def::Descrip { width, height: Some(height), mines } => Board::new(Dim::Rect(width, height), mines),
-        Def::Descrip { width, height: None, mines } => Board::new(Dim::Square(width), mines),
-    }.expect("board to be created without a hitch.");
+    let (def, mut session) = match picked {
+        Some(game) => {
+            if events.recv().is_err() {
+                return;
+            }
+            game
+        },
+        None => {
+            let mut menu = Menu {
+                output: &mut stdout,
+                events: &events,
+                renderer: &renderer,
+                bindings: &bindings,
+            };
+            match menu.main_menu(&mut settings, &stats, &save_path) {
+                Some(game) => game,
+                None => {
+                    write!(stdout, "{}{}Thanks for playing! Farewell.\n\r", termion::clear::All, termion::cursor::Goto(1, 1))
+                        .expect("write to be fine.");
+                    return;
+                },
+            }
+        },
+    };
 
     let mut current_point = (0, 0);
     let mut queued_actions = VecDeque::new();
-    let mut top_left = (0, 0);
-    print_board(&mut stdout, &board, top_left, current_point);
+    let mut press = None;
+    renderer.print_board(&mut stdout, &session, current_point);
 
     loop {
         let input = if queued_actions.is_empty() {
-            match read_input(&mut events) {
+            let next_event = match events.recv_timeout(TICK) {
+                Ok(ev) => ev,
+                Err(RecvTimeoutError::Timeout) => {
+                    renderer.print_status(&mut stdout, &session, current_point);
+                    continue;
+                },
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let res = read_input(next_event, &mut press, &renderer, &bindings);
+            // Redraw whenever the cells pushed in by a held mouse button change.
+            let pressed = press.as_ref()
+                .and_then(|p: &Press| renderer.to_board(p.screen()).map(|loc| p.preview(&bindings, &session.board, loc)))
+                .unwrap_or_default();
+            if pressed != renderer.pressed() {
+                renderer.set_pressed(pressed);
+                renderer.print_board(&mut stdout, &session, current_point);
+            }
+            match res {
                 Ok(Some((action, secondary))) => {
                     if let Some(to_queue) = secondary {
                         queued_actions.push_back(to_queue)
@@ -214,15 +341,63 @@ Press any key to continue.");
         // TODO Get input from terminal.
         let res = match input.action {
             Action::ExitGame => break,
-            Action::LaunchProbe => board.launch_probe(),
-            Action::Mark => board.mark(input.point),
-            Action::Dig => board.dig(input.point),
+            Action::LaunchProbe => session.launch_probe(),
+            Action::ShowStats => {
+                renderer.print_stats(&mut stdout, &stats, settings.capacity, settings.lives, settings.auto_flag);
+                if events.recv().is_err() {
+                    break;
+                }
+                Ok(())
+            },
+            Action::ShowHelp => {
+                renderer.print_help(&mut stdout, &bindings);
+                if events.recv().is_err() {
+                    break;
+                }
+                Ok(())
+            },
+            Action::Pause => {
+                let mut menu = Menu {
+                    output: &mut stdout,
+                    events: &events,
+                    renderer: &renderer,
+                    bindings: &bindings,
+                };
+                match menu.pause_menu(&def, &mut session, &save_path) {
+                    PauseChoice::Resume => (),
+                    PauseChoice::Restart => session = session.restart(),
+                    PauseChoice::NewGame => {
+                        // A loaded game keeps the capacity it was saved with, which its board definition
+                        // may depend on.
+                        let settings = Settings { capacity: session.board.capacity(), ..settings };
+                        session = settings.new_session(&def)
+                            .expect("board to be created, as it was for the last game.");
+                    },
+                    PauseChoice::Quit => break,
+                }
+                Ok(())
+            },
+            Action::Mark => session.mark(input.point),
+            Action::Dig => session.dig(input.point),
+            Action::Chord => session.chord(input.point),
+            Action::FlagChord => session.flag_chord(input.point),
             Action::JumpTo(p) => {
-                if board.is_loc(p) {
+                if session.board.is_loc(p) {
+                    current_point = p;
+                }
+                Ok(())
+            },
+            Action::ViewAt(p) => {
+                if session.board.is_loc(p) {
                     current_point = p;
+                    renderer.center_on(p, (session.board.w(), session.board.h()));
                 }
                 Ok(())
             },
+            Action::ToggleMinimap => {
+                renderer.toggle_minimap();
+                Ok(())
+            },
             Action::Move(d) => {
                 match d {
                     Direction::Up => {
@@ -237,62 +412,84 @@ Press any key to continue.");
                     },
                     Direction::Down => {
                         current_point.1 += 1;
-                        if !board.is_loc(current_point) {
+                        if !session.board.is_loc(current_point) {
                             current_point.1 -= 1
                         }
                     },
                     Direction::Right => {
                         current_point.0 += 1;
-                        if !board.is_loc(current_point) {
+                        if !session.board.is_loc(current_point) {
                             current_point.0 -= 1
                         }
                     },
                 };
                 Ok(())
             },
+            Action::Page(d) => {
+                let page = renderer.viewport().dims;
+                match d {
+                    Direction::Up => current_point.1 = current_point.1.saturating_sub(page.1),
+                    Direction::Left => current_point.0 = current_point.0.saturating_sub(page.0),
+                    Direction::Down => current_point.1 = (current_point.1 + page.1).min(session.board.h() - 1),
+                    Direction::Right => current_point.0 = (current_point.0 + page.0).min(session.board.w() - 1),
+                };
+                Ok(())
+            },
+            Action::Edge(d) => {
+                match d {
+                    Direction::Up => current_point.1 = 0,
+                    Direction::Left => current_point.0 = 0,
+                    Direction::Down => current_point.1 = session.board.h() - 1,
+                    Direction::Right => current_point.0 = session.board.w() - 1,
+                };
+                Ok(())
+            },
         };
 
-        if let Some(new_top_left) = print_board(&mut stdout, &board, top_left, current_point) {
-            top_left = new_top_left;
-        }
+        renderer.print_board(&mut stdout, &session, current_point);
 
         match res {
             Ok(_) => (),
             // Somehow print here.
             Err(Error::OOB) => continue,
             Err(Error::Marked) => continue,
-            Err(Error::Dead) => {
+            // Handled below, through the session's state.
+            Err(Error::Dead(_)) => (),
+        }
+
+        if let (Some(stats), Some(record)) = (&mut stats, Record::from_session(&def, &session)) {
+            stats.record(record);
+            if let Err(e) = stats.save() {
+                write!(stdout, "\n\rCould not save statistics: {}", e)
+                    .expect("write to be fine.");
+            }
+        }
+
+        match session.state() {
+            State::Playing => (),
+            State::Lost => {
                 let size = termion::terminal_size()
                     .expect("no problem getting the terminal size.");
                 write!(stdout, "{}", termion::cursor::Goto(0, size.1 - 1))
                     .expect("write to be fine.");
                 write!(stdout, "You have died!")
                     .expect("write to be fine.");
+                if let Some(analysis) = session.analyze() {
+                    write!(stdout, "\n\r{}", analysis)
+                        .expect("write to be fine.");
+                }
                 break
             },
+            State::Won => {
+                let size = termion::terminal_size()
+                    .expect("no problem getting the terminal size.");
+                write!(stdout, "{}", termion::cursor::Goto(0, size.1 - 1))
+                    .expect("write to be fine.");
+                write!(stdout, "Congratulations!")
+                    .expect("write to be fine.");
+                break;
+            },
         }
-
-        if board.is_all_but_mines_revealed() {
-            let size = termion::terminal_size()
-                .expect("no problem getting the terminal size.");
-            write!(stdout, "{}", termion::cursor::Goto(0, size.1 - 1))
-                .expect("write to be fine.");
-            write!(stdout, "Congratulations!")
-                .expect("write to be fine.");
-            break;
-        }
-
-        write!(stdout, "{}", termion::cursor::Goto(
-                0,
-                (board.h() + 1) as u16,
-        ));
-        Solver::new(&board).calculate_known_cells();
-        println!("\r\n{:?}", current_point);
-        write!(stdout, "{}", termion::cursor::Goto(
-                (current_point.0 + 1) as u16,
-                (current_point.1 + 1) as u16,
-        ));
-        stdout.flush();
     }
 
     write!(stdout, "\n\rThanks for playing! Farewell.\n\r")
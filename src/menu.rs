@@ -0,0 +1,238 @@
+//! Screens shown outside of play: the menu at startup, and the pause menu.
+
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    sync::mpsc::Receiver,
+};
+use termion::{
+    event::{Event, Key, MouseButton, MouseEvent},
+    raw::RawTerminal,
+};
+
+use crate::{
+    board::MAX_CAPACITY,
+    controls::{Action, Bindings, Direction},
+    game::{Session, Settings},
+    opts::{Def, Preset},
+    render::Renderer,
+    save,
+    stats::Store,
+};
+
+// Lives cycle back down to 1 past this in the settings menu. Any number can still be passed on the
+// command line.
+const MAX_MENU_LIVES: u32 = 5;
+
+// What each main menu item does, so that adding an item doesn't shift the ones after it.
+#[derive(Copy, Clone)]
+enum MainItem {
+    Preset(Preset),
+    Custom,
+    Load,
+    Stats,
+    Settings,
+    Controls,
+    Quit,
+}
+
+pub enum PauseChoice {
+    Resume,
+    Restart,
+    NewGame,
+    Quit,
+}
+
+pub struct Menu<'a, W: Write> {
+    pub output: &'a mut RawTerminal<W>,
+    pub events: &'a Receiver<io::Result<Event>>,
+    pub renderer: &'a Renderer,
+    pub bindings: &'a Bindings,
+}
+
+impl<'a, W: Write> Menu<'a, W> {
+    // Returns `None` if the player backs out. `selected` is kept between calls, so that the
+    // selection stays put when the same menu is shown again.
+    fn choose(&mut self, title: &str, items: &[String], selected: &mut usize, message: &str) -> Option<usize> {
+        loop {
+            self.renderer.print_menu(self.output, title, items, *selected, message);
+            let ev = match self.events.recv() {
+                Ok(Ok(ev)) => ev,
+                Ok(Err(_)) => continue,
+                Err(_) => return None,
+            };
+            let action = match ev {
+                Event::Key(Key::Up) => Some(Action::Move(Direction::Up)),
+                Event::Key(Key::Down) => Some(Action::Move(Direction::Down)),
+                Event::Key(Key::Char('\n')) => Some(Action::Dig),
+                Event::Key(Key::Esc) => Some(Action::ExitGame),
+                Event::Key(k) => self.bindings.key(k),
+                // Items start on the third line, and mouse events are 1 indexed.
+                Event::Mouse(MouseEvent::Press(MouseButton::Left, _, y)) => {
+                    match (y as usize).checked_sub(3) {
+                        Some(i) if i < items.len() => {
+                            *selected = i;
+                            Some(Action::Dig)
+                        },
+                        _ => None,
+                    }
+                },
+                _ => None,
+            };
+            match action {
+                Some(Action::Move(Direction::Up)) => *selected = selected.checked_sub(1).unwrap_or(items.len() - 1),
+                Some(Action::Move(Direction::Down)) => *selected = (*selected + 1) % items.len(),
+                Some(Action::Dig) => return Some(*selected),
+                Some(Action::ExitGame) | Some(Action::Pause) => return None,
+                _ => (),
+            }
+        }
+    }
+
+    // Returns `None` if the player backs out.
+    fn prompt(&mut self, question: &str) -> Option<String> {
+        let mut answer = String::new();
+        loop {
+            self.renderer.print_prompt(self.output, question, &answer);
+            match self.events.recv() {
+                Ok(Ok(Event::Key(Key::Char('\n')))) => return Some(answer),
+                Ok(Ok(Event::Key(Key::Esc))) => return None,
+                Ok(Ok(Event::Key(Key::Backspace))) => {
+                    answer.pop();
+                },
+                Ok(Ok(Event::Key(Key::Char(c)))) => answer.push(c),
+                Ok(_) => (),
+                Err(_) => return None,
+            }
+        }
+    }
+
+    // Shows a screen drawn by `print`, until any key is pressed.
+    fn show(&mut self, print: impl FnOnce(&Renderer, &mut RawTerminal<W>)) {
+        print(self.renderer, self.output);
+        let _ = self.events.recv();
+    }
+
+    // Returns the game to play, or `None` to quit.
+    pub fn main_menu(
+        &mut self,
+        settings: &mut Settings,
+        stats: &Option<Store>,
+        save_path: &Option<PathBuf>,
+    ) -> Option<(Def, Session)> {
+        let presets = [Preset::Beginner, Preset::Intermediate, Preset::Advanced];
+        let mut entries: Vec<_> = presets.iter().map(|p| (p.to_string(), MainItem::Preset(*p))).collect();
+        let rest = [
+            ("Custom", MainItem::Custom),
+            ("Load saved game", MainItem::Load),
+            ("Statistics", MainItem::Stats),
+            ("Settings", MainItem::Settings),
+            ("Controls", MainItem::Controls),
+            ("Quit", MainItem::Quit),
+        ];
+        entries.extend(rest.iter().map(|(name, item)| ((*name).to_owned(), *item)));
+        let items: Vec<_> = entries.iter().map(|(name, _)| name.clone()).collect();
+        let (mut selected, mut message) = (0, String::new());
+        loop {
+            let choice = self.choose("Minesweeper", &items, &mut selected, &message)?;
+            message.clear();
+            match entries[choice].1 {
+                MainItem::Preset(preset) => {
+                    let def = Def::Preset(preset);
+                    match settings.new_session(&def) {
+                        Ok(session) => return Some((def, session)),
+                        Err(e) => message = format!("Could not create the board: {}", e),
+                    }
+                },
+                MainItem::Custom => {
+                    let answer = match self.prompt("Enter `width,mines` or `width,height,mines`:") {
+                        Some(answer) => answer,
+                        None => continue,
+                    };
+                    match answer.parse::<Def>() {
+                        Ok(def) => {
+                            let (dim, mines) = def.dim_and_mines();
+                            if mines as usize >= dim.w() * dim.h() {
+                                message = "The board needs room for every mine, and at least one empty cell.".to_owned();
+                                continue;
+                            }
+                            match settings.new_session(&def) {
+                                Ok(session) => return Some((def, session)),
+                                Err(e) => message = format!("Could not create the board: {}", e),
+                            }
+                        },
+                        Err(e) => message = format!("Could not read `{}`: {}", answer, e),
+                    }
+                },
+                MainItem::Load => match save_path {
+                    Some(path) => match save::load(path) {
+                        Ok((def, mut session)) => {
+                            // Budgets depend on the machine rather than the game, so they aren't saved.
+                            session.set_budget(settings.budget);
+                            return Some((def, session));
+                        },
+                        Err(e) => message = format!("Could not load {}: {}", path.display(), e),
+                    },
+                    None => message = "There is nowhere to load games from.".to_owned(),
+                },
+                MainItem::Stats => self.show(|renderer, output| {
+                    renderer.print_stats(output, stats, settings.capacity, settings.lives, settings.auto_flag)
+                }),
+                MainItem::Settings => self.settings_menu(settings),
+                MainItem::Controls => {
+                    let bindings = self.bindings;
+                    self.show(|renderer, output| renderer.print_help(output, bindings));
+                },
+                MainItem::Quit => return None,
+            }
+        }
+    }
+
+    fn settings_menu(&mut self, settings: &mut Settings) {
+        let on_off = |b| if b { "on" } else { "off" };
+        let mut selected = 0;
+        loop {
+            let items = vec![
+                format!("Mines per cell: {}", settings.capacity),
+                format!("Unsure marks: {}", on_off(settings.unsure_marks)),
+                format!("Lives: {}", settings.lives),
+                format!("Auto-flag: {}", on_off(settings.auto_flag)),
+                "Back".to_owned(),
+            ];
+            match self.choose("Settings", &items, &mut selected, "Select a setting to change it.") {
+                Some(0) => settings.capacity = settings.capacity % MAX_CAPACITY + 1,
+                Some(1) => settings.unsure_marks = !settings.unsure_marks,
+                Some(2) => settings.lives = settings.lives % MAX_MENU_LIVES + 1,
+                Some(3) => settings.auto_flag = !settings.auto_flag,
+                _ => return,
+            }
+        }
+    }
+
+    // The board is hidden for as long as the menu is up, and the clock is stopped.
+    pub fn pause_menu(&mut self, def: &Def, session: &mut Session, save_path: &Option<PathBuf>) -> PauseChoice {
+        session.pause();
+        let items: Vec<_> = ["Resume", "Restart", "New game", "Save", "Quit"]
+            .iter()
+            .map(|item| (*item).to_owned())
+            .collect();
+        let (mut selected, mut message) = (0, String::new());
+        let choice = loop {
+            match self.choose("Paused", &items, &mut selected, &message) {
+                None | Some(0) => break PauseChoice::Resume,
+                Some(1) => break PauseChoice::Restart,
+                Some(2) => break PauseChoice::NewGame,
+                Some(3) => message = match save_path {
+                    Some(path) => match save::save(path, def, session) {
+                        Ok(()) => format!("Saved to {}.", path.display()),
+                        Err(e) => format!("Could not save to {}: {}", path.display(), e),
+                    },
+                    None => "There is nowhere to save games to.".to_owned(),
+                },
+                _ => break PauseChoice::Quit,
+            }
+        };
+        session.resume();
+        choice
+    }
+}
@@ -1,5 +1,8 @@
+use std::{path::PathBuf, time::Duration};
 use structopt::StructOpt;
 
+use crate::{board::Dim, glyphs::GlyphSet, solver::{Backend, Budget}};
+
 #[derive(Debug)]
 pub struct PresetDoesNotExist;
 
@@ -15,6 +18,8 @@ pub enum ParseDefError {
     Preset(PresetDoesNotExist),
     TooShort,
     TooMany,
+    NoCells,
+    TooLarge,
 }
 
 impl From<std::num::ParseIntError> for ParseDefError {
@@ -36,11 +41,13 @@ impl std::fmt::Display for ParseDefError {
             Self::Preset(p) => p.fmt(f),
             Self::TooShort => write!(f, "Not enough arguments provided."),
             Self::TooMany => write!(f, "Too many arguments provided."),
+            Self::NoCells => write!(f, "Boards need at least one row and one column."),
+            Self::TooLarge => write!(f, "Board has too many cells to count."),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Preset {
     Beginner,
     Intermediate,
@@ -59,6 +66,16 @@ impl std::fmt::Display for Preset {
     }
 }
 
+impl Preset {
+    pub fn dim_and_mines(&self) -> (Dim, u64) {
+        match self {
+            Self::Beginner => (Dim::Square(9), 10),
+            Self::Intermediate => (Dim::Square(16), 40),
+            Self::Advanced => (Dim::Rect(30, 16), 99),
+        }
+    }
+}
+
 impl std::str::FromStr for Preset {
     type Err = PresetDoesNotExist;
 
@@ -72,7 +89,7 @@ impl std::str::FromStr for Preset {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Def {
     Preset(Preset),
     Descrip {
@@ -82,12 +99,31 @@ pub enum Def {
     }
 }
 
+impl Def {
+    // Uniquely identifies the definition, and can be parsed back into it.
+    pub fn key(&self) -> String {
+        match self {
+            Self::Preset(p) => p.to_string().to_lowercase(),
+            Self::Descrip { width, height: Some(height), mines } => format!("{},{},{}", width, height, mines),
+            Self::Descrip { width, height: None, mines } => format!("{},{},{}", width, width, mines),
+        }
+    }
+
+    pub fn dim_and_mines(&self) -> (Dim, u64) {
+        match self {
+            Self::Preset(p) => p.dim_and_mines(),
+            Self::Descrip { width, height: Some(height), mines } => (Dim::Rect(*width, *height), *mines),
+            Self::Descrip { width, height: None, mines } => (Dim::Square(*width), *mines),
+        }
+    }
+}
+
 impl std::str::FromStr for Def {
     type Err = ParseDefError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts: Vec<_> = s.split(",").collect();
-        match parts.as_slice() {
+        let def = match parts.as_slice() {
             [] => Err(Self::Err::TooShort),
             [preset] => Ok(Self::Preset(preset.parse()?)),
             [dim, mines] => Ok(Self::Descrip {
@@ -101,6 +137,12 @@ impl std::str::FromStr for Def {
                 mines: mines.parse()?,
             }),
             _ => Err(Self::Err::TooMany),
+        }?;
+        let (dim, _) = def.dim_and_mines();
+        match dim.w().checked_mul(dim.h()) {
+            Some(0) => Err(Self::Err::NoCells),
+            Some(_) => Ok(def),
+            None => Err(Self::Err::TooLarge),
         }
     }
 }
@@ -121,9 +163,90 @@ impl std::fmt::Display for Def {
     }
 }
 
+#[derive(Debug)]
+#[derive(StructOpt)]
+pub enum Command {
+    /// Show best times, win streaks and win percentages of past games.
+    Stats,
+    /// Time the solver by having it play whole games on its own, guessing when it has to.
+    Bench {
+        /// Board to play on, in the same format as for a normal game.
+        def: Def,
+        /// Number of games to play.
+        #[structopt(long, default_value = "10")]
+        games: u64,
+        /// Hex encoded seed for the boards and guesses. Random if left out.
+        #[structopt(long)]
+        seed: Option<String>,
+        /// Number of threads to play on, if built with the parallel feature. Every core is used if
+        /// left out.
+        #[structopt(long)]
+        threads: Option<usize>,
+        /// Solver to play with, either linking or sat.
+        #[structopt(long, default_value = "linking")]
+        solver: Backend,
+    },
+}
+
 #[derive(Debug)]
 #[derive(StructOpt)]
 pub struct Opts {
-    #[structopt(default_value = "Def::Preset(Preset::Beginner)")]
-    pub def: Def,
+    /// Board to play on. Either one of beginner, intermediate and advanced, or `width,mines` or
+    /// `width,height,mines`. Shows a menu if left out.
+    pub def: Option<Def>,
+    /// Maximum number of mines a single cell can hold. Numbers show the total mine count in the
+    /// neighbourhood, and marking a cell cycles through the flag counts.
+    #[structopt(short = "k", long, default_value = "1")]
+    pub capacity: u8,
+    /// Disable the unsure (question mark) state when cycling through marks.
+    #[structopt(long)]
+    pub no_unsure: bool,
+    /// Number of mines that can be hit before the game ends. Hit mines are revealed and count as
+    /// flags. Probing while a safe move is left also costs a life.
+    #[structopt(long, default_value = "1")]
+    pub lives: u32,
+    /// Automatically flag cells that can be proven to be mines. Games played this way are recorded
+    /// separately, as assisted.
+    #[structopt(long)]
+    pub auto_flag: bool,
+    /// Play with a screen reader. Commands are typed a line at a time, and the board is described
+    /// in plain text instead of being drawn.
+    #[structopt(long)]
+    pub accessible: bool,
+    /// Where to save games in progress. Defaults to a file in the user's data directory.
+    #[structopt(long, parse(from_os_str))]
+    pub save_file: Option<PathBuf>,
+    /// Where to keep records of finished games. Defaults to a file in the user's data directory.
+    #[structopt(long, parse(from_os_str))]
+    pub stats_file: Option<PathBuf>,
+    /// Color theme. Either one of classic, high-contrast, colorblind and plain, or the path to a
+    /// theme file.
+    #[structopt(long, default_value = "classic")]
+    pub theme: String,
+    /// Characters to draw the board with, either ascii or unicode. Picked from the locale if left
+    /// out.
+    #[structopt(long)]
+    pub glyphs: Option<GlyphSet>,
+    /// Config file with key bindings. Defaults to a file in the user's config directory.
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Milliseconds the solver may spend on a move before it settles for what it has proven so far.
+    /// Unlimited if left out.
+    #[structopt(long, global = true)]
+    pub solve_time: Option<u64>,
+    /// Search steps the solver may take on a move before it settles for what it has proven so far.
+    /// Unlimited if left out.
+    #[structopt(long, global = true)]
+    pub solve_nodes: Option<u64>,
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+impl Opts {
+    pub fn budget(&self) -> Budget {
+        Budget {
+            time: self.solve_time.map(Duration::from_millis),
+            nodes: self.solve_nodes,
+        }
+    }
 }
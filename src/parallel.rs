@@ -0,0 +1,40 @@
+//! Work that can be split into independent pieces, spread over a pool of threads when built with
+//! the `parallel` feature, or done one piece after another otherwise. Results always come back in
+//! the same order as the pieces, so the number of threads never changes the outcome.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[cfg(feature = "parallel")]
+pub fn map<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    items.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn map<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    F: Fn(T) -> R,
+{
+    items.into_iter().map(f).collect()
+}
+
+// Runs `f` with everything it maps spread over `threads` threads, or as many as there are cores
+// if left out. Without the `parallel` feature, everything runs on the current thread regardless.
+#[cfg(feature = "parallel")]
+pub fn with_threads<R: Send>(threads: Option<usize>, f: impl FnOnce() -> R + Send) -> Result<R, ()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .build()
+        .map_err(|_| ())?;
+    Ok(pool.install(f))
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn with_threads<R>(_threads: Option<usize>, f: impl FnOnce() -> R) -> Result<R, ()> {
+    Ok(f())
+}
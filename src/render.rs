@@ -0,0 +1,663 @@
+//! Drawing the game to the terminal, along with the themes that color it.
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+use serde::Deserialize;
+use termion::{color, raw::RawTerminal, style};
+
+use crate::{
+    board::{Cell, CellCategory, CellState},
+    controls::Bindings,
+    game::{Session, State},
+    glyphs::GlyphSet,
+    stats::Store,
+};
+
+// Rows at the bottom of the terminal that the board is not drawn over.
+const STATUS_ROWS: usize = 1;
+// The most room the minimap may take up, not counting its frame.
+const MINIMAP_MAX_DIMS: (usize, usize) = (32, 12);
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl From<io::Error> for ThemeError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ThemeError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Parse(e) => write!(f, "Theme file is invalid: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ColorDoesNotExist(String);
+
+impl fmt::Display for ColorDoesNotExist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Requested color `{}` does not exist.", self.0)
+    }
+}
+
+// Either an index into the terminal's 256 color palette, or a true color.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ThemeColor {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl TryFrom<String> for ThemeColor {
+    type Error = ColorDoesNotExist;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        const NAMES: [&str; 16] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+            "light_black", "light_red", "light_green", "light_yellow",
+            "light_blue", "light_magenta", "light_cyan", "light_white",
+        ];
+        if let Some(i) = NAMES.iter().position(|name| *name == s) {
+            return Ok(Self::Ansi(i as u8));
+        }
+        if let Ok(i) = s.parse() {
+            return Ok(Self::Ansi(i));
+        }
+        if s.len() == 7 && s.is_ascii() && s.starts_with('#') {
+            let channel = |i| u8::from_str_radix(&s[i..i + 2], 16);
+            if let (Ok(r), Ok(g), Ok(b)) = (channel(1), channel(3), channel(5)) {
+                return Ok(Self::Rgb(r, g, b));
+            }
+        }
+        Err(ColorDoesNotExist(s))
+    }
+}
+
+impl color::Color for ThemeColor {
+    fn write_fg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ansi(i) => color::AnsiValue(*i).write_fg(f),
+            Self::Rgb(r, g, b) => color::Rgb(*r, *g, *b).write_fg(f),
+        }
+    }
+
+    fn write_bg(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ansi(i) => color::AnsiValue(*i).write_bg(f),
+            Self::Rgb(r, g, b) => color::Rgb(*r, *g, *b).write_bg(f),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone, Deserialize)]
+pub struct Style {
+    pub fg: Option<ThemeColor>,
+    pub bg: Option<ThemeColor>,
+}
+
+impl Style {
+    // Colors set in `other` take precedence.
+    fn over(self, other: Style) -> Style {
+        Style {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+        }
+    }
+}
+
+// Writes out the escape codes that start the style.
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(fg) = self.fg {
+            write!(f, "{}", color::Fg(fg))?;
+        }
+        if let Some(bg) = self.bg {
+            write!(f, "{}", color::Bg(bg))?;
+        }
+        Ok(())
+    }
+}
+
+// Anything left out of a theme file is drawn in the terminal's default colors.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    // Indexed by the number shown, starting at 1. Larger numbers reuse the last entry.
+    pub numbers: Vec<Style>,
+    pub hidden: Style,
+    pub empty: Style,
+    pub flag: Style,
+    pub unsure: Style,
+    pub exploded: Style,
+    pub cursor: Style,
+    // Shown once the game is lost: mines that were never found, flags on cells without a mine, and
+    // the mines that went off on the last move.
+    pub mine: Style,
+    pub wrong_flag: Style,
+    pub fatal: Style,
+}
+
+const BUILTIN_THEMES: [(&str, &str); 4] = [
+    ("classic", include_str!("../themes/classic.toml")),
+    ("high-contrast", include_str!("../themes/high-contrast.toml")),
+    ("colorblind", include_str!("../themes/colorblind.toml")),
+    ("plain", include_str!("../themes/plain.toml")),
+];
+
+impl Theme {
+    // Accepts either the name of a built in theme or the path to a theme file.
+    pub fn load(name_or_path: &str) -> Result<Self, ThemeError> {
+        let text = match BUILTIN_THEMES.iter().find(|(name, _)| *name == name_or_path) {
+            Some((_, text)) => (*text).to_owned(),
+            None => fs::read_to_string(Path::new(name_or_path))?,
+        };
+        Ok(toml::from_str(&text)?)
+    }
+
+    fn style_of(&self, cell: &Cell) -> Style {
+        match (cell.state, cell.category) {
+            (CellState::Hidden, _) => self.hidden,
+            (CellState::Unsure, _) => self.unsure,
+            (CellState::Marked(_), _) => self.flag,
+            (CellState::Visible, CellCategory::Mine(_)) => self.exploded,
+            (CellState::Visible, CellCategory::Empty(None)) => self.empty,
+            (CellState::Visible, CellCategory::Empty(Some(n))) => self.numbers
+                .get(n as usize - 1)
+                .or_else(|| self.numbers.last())
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+// The part of the board that is currently on screen.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Viewport {
+    pub top_left: (usize, usize),
+    pub dims: (usize, usize),
+}
+
+impl Viewport {
+    // Scrolls the minimum amount needed to keep `point` on screen, without leaving part of the
+    // screen unused if the board is big enough to fill it.
+    pub fn follow(&mut self, point: (usize, usize), board_dims: (usize, usize), screen_dims: (usize, usize)) {
+        fn follow_axis(top: usize, len: usize, point: usize, board_len: usize) -> usize {
+            let top = if point < top {
+                point
+            } else if point >= top + len {
+                point + 1 - len
+            } else {
+                top
+            };
+            top.min(board_len - len)
+        }
+
+        self.dims = (screen_dims.0.min(board_dims.0), screen_dims.1.min(board_dims.1));
+        self.top_left = (
+            follow_axis(self.top_left.0, self.dims.0, point.0, board_dims.0),
+            follow_axis(self.top_left.1, self.dims.1, point.1, board_dims.1),
+        );
+    }
+
+    pub fn to_board(self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        if x < self.dims.0 && y < self.dims.1 {
+            Some((self.top_left.0 + x, self.top_left.1 + y))
+        } else {
+            None
+        }
+    }
+
+    // Scrolls so that `point` is as close to the center of the screen as the board allows.
+    pub fn center_on(&mut self, point: (usize, usize), board_dims: (usize, usize)) {
+        self.top_left = (
+            point.0.saturating_sub(self.dims.0 / 2).min(board_dims.0.saturating_sub(self.dims.0)),
+            point.1.saturating_sub(self.dims.1 / 2).min(board_dims.1.saturating_sub(self.dims.1)),
+        );
+    }
+
+    pub fn to_screen(self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        let screen = (x.checked_sub(self.top_left.0)?, y.checked_sub(self.top_left.1)?);
+        if screen.0 < self.dims.0 && screen.1 < self.dims.1 {
+            Some(screen)
+        } else {
+            None
+        }
+    }
+}
+
+// A downsampled overview of the whole board, drawn in the top right corner of the terminal. Each
+// block of the minimap covers `scale` cells of the board.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Minimap {
+    // Screen location of the first block, just inside of the frame.
+    origin: (usize, usize),
+    dims: (usize, usize),
+    scale: (usize, usize),
+    board_dims: (usize, usize),
+}
+
+impl Minimap {
+    fn layout(board_dims: (usize, usize), screen_dims: (usize, usize)) -> Option<Self> {
+        // Keep at least two thirds of the screen for the board itself.
+        let max_dims = (
+            MINIMAP_MAX_DIMS.0.min((screen_dims.0 / 3).saturating_sub(2)),
+            MINIMAP_MAX_DIMS.1.min((screen_dims.1 / 3).saturating_sub(2)),
+        );
+        if max_dims.0 == 0 || max_dims.1 == 0 || board_dims.0 == 0 || board_dims.1 == 0 {
+            return None;
+        }
+        let scale = (board_dims.0.div_ceil(max_dims.0), board_dims.1.div_ceil(max_dims.1));
+        let dims = (board_dims.0.div_ceil(scale.0), board_dims.1.div_ceil(scale.1));
+        Some(Self {
+            origin: (screen_dims.0 - dims.0 - 1, 1),
+            dims,
+            scale,
+            board_dims,
+        })
+    }
+
+    // The board cell at the center of the block under the screen location.
+    pub fn to_board(self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        let block = (x.checked_sub(self.origin.0)?, y.checked_sub(self.origin.1)?);
+        if block.0 >= self.dims.0 || block.1 >= self.dims.1 {
+            return None;
+        }
+        Some((
+            (block.0 * self.scale.0 + self.scale.0 / 2).min(self.board_dims.0 - 1),
+            (block.1 * self.scale.1 + self.scale.1 / 2).min(self.board_dims.1 - 1),
+        ))
+    }
+
+    fn cells_in(&self, block: (usize, usize)) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let start = (block.0 * self.scale.0, block.1 * self.scale.1);
+        (
+            start.0..(start.0 + self.scale.0).min(self.board_dims.0),
+            start.1..(start.1 + self.scale.1).min(self.board_dims.1),
+        )
+    }
+
+    // Untouched blocks get the first shade and fully revealed ones the last, with the rest spread
+    // over the shades in between.
+    fn shade(revealed: usize, total: usize, shades: usize) -> usize {
+        if revealed == 0 {
+            0
+        } else if revealed == total {
+            shades - 1
+        } else {
+            1 + revealed * (shades - 2) / total
+        }
+    }
+}
+
+pub struct Renderer {
+    theme: Theme,
+    glyphs: GlyphSet,
+    viewport: Viewport,
+    show_minimap: bool,
+    // Where the minimap was last drawn, if it was.
+    minimap: Option<Minimap>,
+    // Hidden cells drawn pushed in, while a mouse button is held over them.
+    pressed: Vec<(usize, usize)>,
+}
+
+impl Renderer {
+    pub fn new(theme: Theme, glyphs: GlyphSet) -> Self {
+        Self {
+            theme,
+            glyphs,
+            viewport: Viewport::default(),
+            show_minimap: false,
+            minimap: None,
+            pressed: vec![],
+        }
+    }
+
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    pub fn pressed(&self) -> &[(usize, usize)] {
+        &self.pressed
+    }
+
+    pub fn set_pressed(&mut self, pressed: Vec<(usize, usize)>) {
+        self.pressed = pressed;
+    }
+
+    pub fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+    }
+
+    // The minimap is drawn over the board, so it takes priority.
+    pub fn to_board(&self, screen: (usize, usize)) -> Option<(usize, usize)> {
+        match self.minimap.and_then(|minimap| minimap.to_board(screen)) {
+            Some(loc) => Some(loc),
+            None => self.viewport.to_board(screen),
+        }
+    }
+
+    pub fn is_on_minimap(&self, screen: (usize, usize)) -> bool {
+        self.minimap.and_then(|minimap| minimap.to_board(screen)).is_some()
+    }
+
+    pub fn center_on(&mut self, point: (usize, usize), board_dims: (usize, usize)) {
+        self.viewport.center_on(point, board_dims);
+    }
+
+    pub fn print_status<W: Write>(
+        &self,
+        output: &mut RawTerminal<W>,
+        session: &Session,
+        current_point: (usize, usize),
+    ) {
+        let size = termion::terminal_size().expect("no problem getting the terminal size.");
+        if size.1 == 0 {
+            return;
+        }
+        let elapsed = session.elapsed().as_secs();
+        let mut status = format!(
+            "{} | {:02}:{:02} | Mines left: {} | Probes: {} | ({}, {})",
+            session.state(),
+            elapsed / 60,
+            elapsed % 60,
+            session.remaining_mines(),
+            session.probes(),
+            current_point.0,
+            current_point.1,
+        );
+        if session.initial_lives() > 1 {
+            status += &format!(" | Lives: {}", session.lives());
+        }
+        if session.is_assisted() {
+            status += " | Auto-flag";
+        }
+        status.truncate(size.0 as usize);
+
+        write!(output, "{}{}{}", termion::cursor::Goto(1, size.1), termion::clear::CurrentLine, status)
+            .expect("write to be fine.");
+        if let Some((x, y)) = self.viewport.to_screen(current_point) {
+            write!(output, "{}", termion::cursor::Goto((x + 1) as u16, (y + 1) as u16))
+                .expect("write to be fine.");
+        }
+        output.flush().expect("flush to be fine.");
+    }
+
+    pub fn print_board<W: Write>(
+        &mut self,
+        output: &mut RawTerminal<W>,
+        session: &Session,
+        current_point: (usize, usize),
+    ) {
+        let size = termion::terminal_size().expect("no problem getting the terminal size.");
+        let size = (size.0 as usize, (size.1 as usize).saturating_sub(STATUS_ROWS));
+        if size.0 == 0 || size.1 == 0 {
+            return;
+        }
+        let board = &session.board;
+        self.viewport.follow(current_point, (board.w(), board.h()), size);
+        let top_left = self.viewport.top_left;
+
+        let snippet = board.display(self.viewport.dims, top_left).expect("no problem with updating the screen.");
+
+        let lost = session.state() == State::Lost;
+        let fatal_cells = if lost { session.fatal_cells() } else { vec![] };
+        // Pushed in cells look like they've already been revealed.
+        let pushed = Cell {
+            state: CellState::Visible,
+            category: CellCategory::Empty(None),
+            scratch: false,
+        };
+        write!(output, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1))
+           .expect("write to be fine.");
+        for (row, cells) in snippet.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                let loc = (top_left.0 + col, top_left.1 + row);
+                let cell = if self.pressed.contains(&loc) { &pushed } else { cell };
+                let (glyph, mut cell_style) = if lost {
+                    self.look_after_loss(cell, fatal_cells.contains(&loc))
+                } else {
+                    (self.glyphs.cell(cell), self.theme.style_of(cell))
+                };
+                if loc == current_point {
+                    cell_style = cell_style.over(self.theme.cursor);
+                }
+                if cell_style == Style::default() {
+                    write!(output, "{}", glyph)
+                } else {
+                    write!(output, "{}{}{}", cell_style, glyph, style::Reset)
+                }.expect("output to standard out without an issue.");
+            }
+            write!(output, "\n\r").expect("write to be fine.");
+        }
+        self.minimap = if self.show_minimap {
+            Minimap::layout((board.w(), board.h()), size)
+        } else {
+            None
+        };
+        if let Some(minimap) = self.minimap {
+            self.print_minimap(output, session, minimap);
+        }
+        self.print_status(output, session, current_point);
+    }
+
+    // Every mine is shown once the game is lost, along with any flags that were wrong.
+    fn look_after_loss(&self, cell: &Cell, is_fatal: bool) -> (char, Style) {
+        match (cell.state, cell.is_mine()) {
+            (CellState::Hidden, true) | (CellState::Unsure, true) => (self.glyphs.mine(), self.theme.mine),
+            (CellState::Marked(_), false) => (self.glyphs.wrong_flag(), self.theme.wrong_flag),
+            (CellState::Visible, true) if is_fatal => (self.glyphs.cell(cell), self.theme.fatal),
+            _ => (self.glyphs.cell(cell), self.theme.style_of(cell)),
+        }
+    }
+
+    fn print_minimap<W: Write>(&self, output: &mut RawTerminal<W>, session: &Session, minimap: Minimap) {
+        let (x0, y0) = (minimap.origin.0 as u16, minimap.origin.1 as u16);
+        let shades = self.glyphs.shades();
+        let [horizontal, vertical, top_left, top_right, bottom_right, bottom_left] = self.glyphs.frame();
+        let horizontal = horizontal.to_string().repeat(minimap.dims.0);
+        write!(output, "{}{}{}{}", termion::cursor::Goto(x0, y0), top_left, horizontal, top_right)
+            .expect("write to be fine.");
+        for block_y in 0..minimap.dims.1 {
+            write!(output, "{}{}", termion::cursor::Goto(x0, y0 + 1 + block_y as u16), vertical)
+                .expect("write to be fine.");
+            for block_x in 0..minimap.dims.0 {
+                let (cols, rows) = minimap.cells_in((block_x, block_y));
+                let (mut total, mut revealed, mut flagged, mut exploded) = (0, 0, false, false);
+                for y in rows.clone() {
+                    for x in cols.clone() {
+                        let cell = &session.board.cells[y][x];
+                        total += 1;
+                        if cell.state == CellState::Visible {
+                            revealed += 1;
+                        }
+                        flagged |= cell.is_marked();
+                        exploded |= cell.is_exploded();
+                    }
+                }
+                let shade = Minimap::shade(revealed, total, shades.len());
+                let block_style = if exploded {
+                    self.theme.exploded
+                } else if flagged {
+                    self.theme.flag
+                } else {
+                    self.theme.hidden
+                };
+                // The part of the board that's on screen is drawn inverted.
+                let viewport = &self.viewport;
+                let on_screen = cols.start < viewport.top_left.0 + viewport.dims.0
+                    && viewport.top_left.0 < cols.end
+                    && rows.start < viewport.top_left.1 + viewport.dims.1
+                    && viewport.top_left.1 < rows.end;
+                let invert = if on_screen { format!("{}", style::Invert) } else { String::new() };
+                write!(output, "{}{}{}{}", block_style, invert, shades[shade], style::Reset)
+                    .expect("write to be fine.");
+            }
+            write!(output, "{}", vertical).expect("write to be fine.");
+        }
+        write!(
+            output,
+            "{}{}{}{}",
+            termion::cursor::Goto(x0, y0 + 1 + minimap.dims.1 as u16),
+            bottom_left,
+            horizontal,
+            bottom_right,
+        ).expect("write to be fine.");
+    }
+
+    pub fn print_stats<W: Write>(
+        &self,
+        output: &mut RawTerminal<W>,
+        stats: &Option<Store>,
+        capacity: u8,
+        lives: u32,
+        assisted: bool,
+    ) {
+        write!(output, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1))
+           .expect("write to be fine.");
+        match stats {
+            Some(stats) => {
+                for (def, summary) in stats.report(capacity, lives, assisted) {
+                    write!(output, "{}: {}\n\r", def, summary).expect("write to be fine.");
+                }
+            },
+            None => write!(output, "Statistics are unavailable.\n\r").expect("write to be fine."),
+        }
+        write!(output, "\n\rPress any key to return to the game.").expect("write to be fine.");
+        output.flush().expect("flush to be fine.");
+    }
+
+    // Items are listed one per line, from the third line down.
+    pub fn print_menu<W: Write>(
+        &self,
+        output: &mut RawTerminal<W>,
+        title: &str,
+        items: &[String],
+        selected: usize,
+        message: &str,
+    ) {
+        write!(output, "{}{}{}\n\r\n\r", termion::clear::All, termion::cursor::Goto(1, 1), title)
+           .expect("write to be fine.");
+        for (i, item) in items.iter().enumerate() {
+            if i == selected {
+                write!(output, "{}> {}{}\n\r", self.theme.cursor, item, style::Reset)
+            } else {
+                write!(output, "  {}\n\r", item)
+            }.expect("write to be fine.");
+        }
+        write!(output, "\n\r{}", message).expect("write to be fine.");
+        output.flush().expect("flush to be fine.");
+    }
+
+    pub fn print_prompt<W: Write>(&self, output: &mut RawTerminal<W>, question: &str, answer: &str) {
+        write!(
+            output,
+            "{}{}{}\n\r\n\r> {}",
+            termion::clear::All,
+            termion::cursor::Goto(1, 1),
+            question,
+            answer,
+        ).expect("write to be fine.");
+        output.flush().expect("flush to be fine.");
+    }
+
+    pub fn print_help<W: Write>(&self, output: &mut RawTerminal<W>, bindings: &Bindings) {
+        write!(output, "{}{}", termion::clear::All, termion::cursor::Goto(1, 1))
+           .expect("write to be fine.");
+        for line in bindings.help() {
+            write!(output, "{}\n\r", line).expect("write to be fine.");
+        }
+        write!(output, "\n\rPress any key to return to the game.").expect("write to be fine.");
+        output.flush().expect("flush to be fine.");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+
+    use super::{Minimap, Theme, ThemeColor, Viewport, BUILTIN_THEMES};
+
+    #[test]
+    fn color_test() {
+        assert_eq!(ThemeColor::try_from("light_blue".to_owned()), Ok(ThemeColor::Ansi(12)));
+        assert_eq!(ThemeColor::try_from("208".to_owned()), Ok(ThemeColor::Ansi(208)));
+        assert_eq!(ThemeColor::try_from("#0072b2".to_owned()), Ok(ThemeColor::Rgb(0x00, 0x72, 0xb2)));
+        assert!(ThemeColor::try_from("#0072b".to_owned()).is_err());
+        assert!(ThemeColor::try_from("chartreuse".to_owned()).is_err());
+    }
+
+    #[test]
+    fn builtin_theme_test() {
+        for (name, _) in BUILTIN_THEMES.iter() {
+            Theme::load(name).expect("built in theme to parse.");
+        }
+        assert_eq!(Theme::load("plain").expect("built in theme to parse."), Theme::default());
+    }
+
+    #[test]
+    fn viewport_test() {
+        let mut viewport = Viewport::default();
+        viewport.follow((0, 0), (200, 200), (80, 24));
+        assert_eq!(viewport.top_left, (0, 0));
+
+        // Scrolls one cell at a time when the cursor walks off the edge.
+        viewport.follow((80, 24), (200, 200), (80, 24));
+        assert_eq!(viewport.top_left, (1, 1));
+        assert_eq!(viewport.to_board((79, 23)), Some((80, 24)));
+        assert_eq!(viewport.to_board((80, 23)), None);
+        assert_eq!(viewport.to_screen((0, 0)), None);
+
+        // Never scrolls past the end of the board, even when the terminal grows.
+        viewport.follow((199, 199), (200, 200), (80, 24));
+        viewport.follow((199, 199), (200, 200), (100, 50));
+        assert_eq!(viewport.top_left, (100, 150));
+
+        // Small boards stay put.
+        viewport.follow((5, 5), (9, 9), (80, 24));
+        assert_eq!(viewport.top_left, (0, 0));
+        assert_eq!(viewport.dims, (9, 9));
+    }
+
+    #[test]
+    fn minimap_test() {
+        let minimap = Minimap::layout((200, 100), (120, 40)).expect("minimap to fit on screen.");
+        assert_eq!(minimap.scale, (7, 10));
+        assert_eq!(minimap.dims, (29, 10));
+
+        let (x0, y0) = minimap.origin;
+        assert_eq!(minimap.to_board((x0, y0)), Some((3, 5)));
+        assert_eq!(minimap.to_board((x0 + 28, y0 + 9)), Some((199, 95)));
+        assert_eq!(minimap.to_board((x0 + 29, y0)), None);
+        assert_eq!(minimap.to_board((x0 - 1, y0)), None);
+
+        assert_eq!(Minimap::layout((200, 100), (6, 6)), None);
+
+        // Every shade gets used, and a mostly revealed block gets the darkest partial one.
+        let block = minimap.scale.0 * minimap.scale.1;
+        assert_eq!(Minimap::shade(0, block, 5), 0);
+        assert_eq!(Minimap::shade(10, block, 5), 1);
+        assert_eq!(Minimap::shade(40, block, 5), 2);
+        assert_eq!(Minimap::shade(60, block, 5), 3);
+        assert_eq!(Minimap::shade(block - 1, block, 5), 3);
+        assert_eq!(Minimap::shade(block, block, 5), 4);
+    }
+}
@@ -0,0 +1,252 @@
+//! Games in progress, kept in a file so that they can be picked up again later.
+
+use std::{
+    convert::TryInto,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    board::{Board, BoardError, CellCategory, CellState},
+    game::{Session, State},
+    opts::{Def, ParseDefError},
+    util,
+};
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Read(toml::de::Error),
+    Write(toml::ser::Error),
+    Def(ParseDefError),
+    Cells(BoardError),
+    // A stacked mine or flag count for a cell that isn't on the board, or doesn't hold a mine or
+    // flag to stack.
+    Stacked((usize, usize)),
+    Seed,
+    Finished,
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for SaveError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl From<toml::ser::Error> for SaveError {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::Write(e)
+    }
+}
+
+impl From<ParseDefError> for SaveError {
+    fn from(e: ParseDefError) -> Self {
+        Self::Def(e)
+    }
+}
+
+impl From<BoardError> for SaveError {
+    fn from(e: BoardError) -> Self {
+        Self::Cells(e)
+    }
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Read(e) => write!(f, "Save file is corrupt: {}", e),
+            Self::Write(e) => write!(f, "Could not serialize game: {}", e),
+            Self::Def(e) => write!(f, "Save file has a bad board definition: {}", e),
+            Self::Cells(e) => write!(f, "Saved board is malformed: {}", e),
+            Self::Stacked((x, y)) => write!(f, "Saved board has nothing to stack at ({}, {}).", x, y),
+            Self::Seed => write!(f, "Save file has a malformed seed."),
+            Self::Finished => write!(f, "Only games in progress can be saved."),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveFile {
+    // The board definition, in the same format accepted on the command line.
+    def: String,
+    capacity: u8,
+    unsure_marks: bool,
+    #[serde(default)]
+    auto_flag: bool,
+    initial_lives: u32,
+    lives: u32,
+    clicks: u64,
+    probes: u64,
+    time_ms: u64,
+    // Hex encoded. Missing if the board wasn't randomly generated.
+    seed: Option<String>,
+    // In the same format as the boards under `testing/boards`.
+    board: String,
+    // Cells holding more than one mine or flag, as [x, y, count], since the board can only show
+    // one of each.
+    #[serde(default)]
+    stacked_mines: Vec<(usize, usize, u8)>,
+    #[serde(default)]
+    stacked_flags: Vec<(usize, usize, u8)>,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("minesweeper").join("save.toml"))
+}
+
+pub fn save(path: &Path, def: &Def, session: &Session) -> Result<(), SaveError> {
+    if session.state() != State::Playing {
+        return Err(SaveError::Finished);
+    }
+    let board = &session.board;
+    let (mut stacked_mines, mut stacked_flags) = (vec![], vec![]);
+    for (x, y) in board.all_locs() {
+        let cell = board.cells[y][x];
+        if let CellCategory::Mine(n) = cell.category {
+            if n > 1 {
+                stacked_mines.push((x, y, n));
+            }
+        }
+        if let CellState::Marked(n) = cell.state {
+            if n > 1 {
+                stacked_flags.push((x, y, n));
+            }
+        }
+    }
+    let file = SaveFile {
+        def: def.key(),
+        capacity: board.capacity(),
+        unsure_marks: board.unsure_marks(),
+        auto_flag: session.is_assisted(),
+        initial_lives: session.initial_lives(),
+        lives: session.lives(),
+        clicks: session.clicks(),
+        probes: session.probes(),
+        time_ms: session.elapsed().as_millis() as u64,
+        seed: board.seed().map(|seed| util::to_hex(&seed)),
+        board: board.to_save(),
+        stacked_mines,
+        stacked_flags,
+    };
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, toml::to_string(&file)?)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<(Def, Session), SaveError> {
+    read(&fs::read_to_string(path)?)
+}
+
+// The contents of a save file, already read in.
+pub fn read(text: &str) -> Result<(Def, Session), SaveError> {
+    let file: SaveFile = toml::from_str(text)?;
+    let def: Def = file.def.parse()?;
+    // New games are started from the definition, so there has to be room for its mines.
+    let (dim, mines) = def.dim_and_mines();
+    Board::check_room(dim, mines, file.capacity)?;
+
+    // Counts that are too high for the capacity are turned away along with the rest of the cells.
+    let mut cells = Board::from_save(file.board.as_bytes())?.cells;
+    for (x, y, n) in file.stacked_mines {
+        match cells.get_mut(y).and_then(|row| row.get_mut(x)) {
+            Some(cell) if cell.is_mine() => cell.category = CellCategory::Mine(n),
+            _ => return Err(SaveError::Stacked((x, y))),
+        }
+    }
+    for (x, y, n) in file.stacked_flags {
+        match cells.get_mut(y).and_then(|row| row.get_mut(x)) {
+            Some(cell) if cell.is_marked() => cell.state = CellState::Marked(n),
+            _ => return Err(SaveError::Stacked((x, y))),
+        }
+    }
+    let mut board = Board::from_cells(cells, file.capacity)?;
+    board.set_unsure_marks(file.unsure_marks);
+    board.set_seed(match file.seed {
+        Some(hex) => Some(
+            util::from_hex(&hex)
+                .and_then(|seed| seed.as_slice().try_into().ok())
+                .ok_or(SaveError::Seed)?,
+        ),
+        None => None,
+    });
+
+    let mut session = Session::restore(
+        board,
+        file.initial_lives,
+        file.lives,
+        file.clicks,
+        file.probes,
+        Duration::from_millis(file.time_ms),
+    );
+    session.set_auto_flag(file.auto_flag);
+    Ok((def, session))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::{
+        board::{Board, BoardError, CellCategory, CellState, Dim},
+        game::Session,
+    };
+    use super::{load, read, save, SaveError};
+
+    #[test]
+    fn round_trip_test() {
+        let board = Board::new_fixed(Dim::Rect(4, 3), 2, vec![(0, 0), (0, 0), (3, 2)])
+            .expect("board to be created without a hitch.");
+        let mut session = Session::new(board, 2);
+        session.mark((0, 0)).expect("location to be on the board.");
+        session.mark((0, 0)).expect("location to be on the board.");
+        session.dig((3, 0)).expect("location to be safe.");
+
+        let path = env::temp_dir().join(format!("minesweeper-save-test-{}.toml", std::process::id()));
+        save(&path, &"4,3,3".parse().expect("def to parse."), &session).expect("game to save.");
+        let (def, loaded) = load(&path).expect("game to load.");
+        std::fs::remove_file(&path).expect("save file to be removable.");
+
+        assert_eq!(def.key(), "4,3,3");
+        assert_eq!(loaded.board.to_save(), session.board.to_save());
+        assert_eq!(loaded.board.cells[0][0].state, CellState::Marked(2));
+        assert_eq!(loaded.board.cells[0][0].category, CellCategory::Mine(2));
+        assert_eq!(loaded.clicks(), 3);
+        assert_eq!(loaded.lives(), 2);
+    }
+
+    #[test]
+    fn read_error_test() {
+        let text = include_str!("../testing/fuzz/save/stacked.toml");
+        read(text).expect("game to load.");
+
+        match read(&text.replace("stacked_mines = [[0, 0, 2]]", "stacked_mines = [[1, 0, 2]]")) {
+            Err(SaveError::Stacked((1, 0))) => (),
+            res => panic!("expected a stacked count with nothing to stack, got {:?}", res.map(|_| ())),
+        }
+        match read(&text.replace("stacked_flags = [[0, 0, 2]]", "stacked_flags = [[0, 0, 3]]")) {
+            Err(SaveError::Cells(BoardError::Overfull((0, 0)))) => (),
+            res => panic!("expected an overfull cell, got {:?}", res.map(|_| ())),
+        }
+        match read(&text.replace("def = \"4,3,3\"", "def = \"4,3,25\"")) {
+            Err(SaveError::Cells(BoardError::TooManyMines(25, 24))) => (),
+            res => panic!("expected too many mines, got {:?}", res.map(|_| ())),
+        }
+        match read(&text.replace("seed = \"2a", "seed = \"2z")) {
+            Err(SaveError::Seed) => (),
+            res => panic!("expected a bad seed, got {:?}", res.map(|_| ())),
+        }
+    }
+}
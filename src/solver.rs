@@ -17,24 +17,39 @@
 //! Lastly, we solve the CSP of the components, shortcircuiting on impossible
 //! situations. This CSP involves the target board mine quantity.
 
+mod cell_set;
 mod region;
 mod algo;
+mod probability;
+mod incremental;
+mod backend;
+mod sat;
+mod budget;
 
-use indexmap::IndexSet;
-use std::collections::VecDeque;
+pub use cell_set::CellSet;
+pub use backend::{Backend, Solution};
+pub use budget::Budget;
+pub use probability::mine_probabilities;
+pub use incremental::IncrementalSolver;
+
+use indexmap::{IndexMap, IndexSet};
 use crate::{
     board::Board,
-    solver::region::{Region, StrippedRegions, LinkedSubRegion},
+    solver::{
+        backend::Constraints,
+        budget::Allowance,
+        region::{Region, LinkedSubRegion},
+    },
 };
 
 // TODO Make this entire process more efficient. Cause it should be possible.
 pub struct Solver<'a> {
     pub board: &'a Board,
 
+    backend: Backend,
+    budget: Budget,
     board_region: Region,
     valid_regions: Vec<Region>,
-    found_empty_locs: IndexSet<(usize, usize)>,
-    found_mine_locs: IndexSet<(usize, usize)>,
 }
 
 impl<'a> Solver<'a> {
@@ -49,113 +64,396 @@ impl<'a> Solver<'a> {
     }
 
     pub fn new(board: &'a Board) -> Self {
+        Self::with_backend(board, Backend::default())
+    }
+
+    pub fn with_backend(board: &'a Board, backend: Backend) -> Self {
         Self {
             board,
+            backend,
+            budget: Budget::unlimited(),
             board_region: Region::board(board),
             valid_regions: Self::extract_regions(board),
-            found_empty_locs: IndexSet::new(),
-            found_mine_locs: IndexSet::new(),
         }
     }
+
+    pub fn set_budget(&mut self, budget: Budget) {
+        self.budget = budget;
+    }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct KnownCells {
     pub empty: IndexSet<(usize, usize)>,
     pub mines: IndexSet<(usize, usize)>,
 }
 
-// The whole point of this struct.
-impl<'a> Solver<'a> {
-    fn strip_mine_and_empty_regions(&mut self) -> KnownCells {
-        let regions = self.valid_regions.drain(..).collect();
-        let StrippedRegions { locs: zero_locs, regions } = Region::strip_zero_regions_from(regions);
-        let StrippedRegions { locs: mine_locs, regions } = Region::strip_mine_regions_from(regions);
-        self.valid_regions = regions;
-        self.found_empty_locs.extend(zero_locs.iter());
-        self.found_mine_locs.extend(mine_locs.iter());
+impl KnownCells {
+    pub fn is_empty(&self) -> bool {
+        self.empty.is_empty() && self.mines.is_empty()
+    }
+}
+
+// Known cells, while they're still being worked out.
+struct Facts {
+    empty: CellSet,
+    mines: CellSet,
+}
+
+impl Facts {
+    fn new(width: usize) -> Self {
+        Self {
+            empty: CellSet::new(width),
+            mines: CellSet::new(width),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.empty.is_empty() && self.mines.is_empty()
+    }
+
+    fn into_known_cells(self) -> KnownCells {
         KnownCells {
-            empty: zero_locs,
-            mines: mine_locs,
+            empty: self.empty.iter().collect(),
+            mines: self.mines.iter().collect(),
         }
     }
+}
 
-    // Error when board state contradicts itself. Typically due to error in placing a flagged cell.
-    pub fn calculate_known_cells(&mut self) -> Result<Option<KnownCells>, ()> {
-        self.strip_mine_and_empty_regions();
-        // Find linked
-        let mut links = self.valid_regions.iter()
-            .enumerate()
-            // Unique cartesian product
-            .flat_map(|(i, p0)| self.valid_regions[i..].iter().map(move |p1| (p0, p1)))
-            .filter_map(|(p0, p1)| LinkedSubRegion::deduce_links(p0, p1))
-            .collect::<VecDeque<_>>();
-        let mut since_last_change = 0;
-        while let Some(link) = links.pop_front() {
-            if link.mine_sets.len() != 1 { // Do nothing, as more than one variant exists and we don't do guesses.
-                links.push_back(link);
-            } else { // Only one variant exists.
-                let LinkedSubRegion { r0, rs, r1, mut mine_sets } = link;
-                let (m0, ms, m1) = mine_sets.pop()
-                    .expect("the element that was just reported to be there.");
-                assert!(mine_sets.is_empty(), "mine_sets to have no more elements.");
-                let mut link_zero_locs = IndexSet::new();
-                let mut link_mine_locs = IndexSet::new();
-                if m0 == 0 {
-                    link_zero_locs.extend(r0);
-                    since_last_change = 0;
-                } else if m0 == r0.len() {
-                    link_mine_locs.extend(r0);
-                    since_last_change = 0;
-                }
-                if m1 == 0 {
-                    link_zero_locs.extend(r1);
-                    since_last_change = 0;
-                } else if m1 == r1.len() {
-                    link_mine_locs.extend(r1);
-                    since_last_change = 0;
-                }
-                if ms == 0 {
-                    link_zero_locs.extend(rs);
-                    since_last_change = 0;
-                } else if ms == rs.len() {
-                    link_mine_locs.extend(rs);
-                    since_last_change = 0;
-                }
-                for link in &mut links {
-                    link.remove_mines(&link_mine_locs);
-                    link.remove_empty(&link_zero_locs);
-                    // TODO There are more conclusions available than just this. Figure out what
-                    // they are.
+// Draws every conclusion the regions and links allow. Each conclusion only ever narrows things
+// down, so the same cells are found no matter what order they're found in, which is what lets
+// the incremental solver work on one part of the board at a time.
+//
+// Whenever a link pins down the number of mines in one of its parts, without settling every cell
+// in it, the part becomes a region of its own, and is linked to every region it overlaps. That
+// way, conclusions that take three or more numbers together are reached a pair at a time.
+//
+// Every round of conclusions takes a step out of the `allowance`. If it runs out, only what was
+// found in the rounds before is kept, and the solution is marked incomplete.
+//
+// Errors when the regions contradict each other, which can only happen if a flag is wrong.
+fn deduce(
+    width: usize,
+    mut regions: Vec<Region>,
+    mut links: Vec<LinkedSubRegion>,
+    allowance: &Allowance,
+) -> Result<Solution, ()> {
+    let mut found = Facts::new(width);
+    // Found, but not yet removed from the regions and links.
+    let mut pending = Facts::new(width);
+    // Every set of cells that has been a region, so that none is made into one twice.
+    let mut seen: IndexSet<CellSet> = regions.iter().map(|region| region.hidden().clone()).collect();
+    let mut covering: IndexMap<(usize, usize), Vec<usize>> = IndexMap::new();
+    for (i, region) in regions.iter().enumerate() {
+        for loc in region.hidden().iter() {
+            covering.entry(loc).or_default().push(i);
+        }
+    }
+    loop {
+        if !pending.empty.is_disjoint(&pending.mines) {
+            return Err(());
+        }
+        for region in &mut regions {
+            region.remove_mine_locs(&pending.mines)?;
+            region.remove_empty_locs(&pending.empty);
+            if !region.is_consistent() {
+                return Err(());
+            }
+        }
+        for link in &mut links {
+            link.remove_mines(&pending.mines);
+            link.remove_empty(&pending.empty);
+            if link.mine_sets.is_empty() {
+                return Err(());
+            }
+        }
+        found.empty.union_with(&pending.empty);
+        found.mines.union_with(&pending.mines);
+        pending = Facts::new(width);
+        if !allowance.spend() {
+            return Ok(Solution {
+                known: found.into_known_cells(),
+                probabilities: None,
+                complete: false,
+            });
+        }
+
+        for region in &regions {
+            if region.is_all_empty() {
+                pending.empty.union_with(region.hidden());
+            } else if region.is_all_mines() {
+                pending.mines.union_with(region.hidden());
+            }
+        }
+        let mut derived = vec![];
+        for link in &links {
+            // Only what every way of splitting the mines agrees on, as we don't do guesses.
+            for (r, (least, most)) in link.parts().iter().zip(link.bounds().iter()) {
+                if r.is_empty() {
+                    continue;
                 }
-                for region in &mut self.valid_regions {
-                    region.remove_mine_locs(&link_mine_locs);
-                    region.remove_empty_locs(&link_zero_locs);
+                if *most == 0 {
+                    pending.empty.union_with(r);
+                } else if *least == r.len() * link.capacity {
+                    pending.mines.union_with(r);
+                } else if least == most && !seen.contains(*r) {
+                    derived.push(Region::new(*least, link.capacity, (*r).clone()));
                 }
-                self.found_mine_locs.extend(link_mine_locs);
-                self.found_empty_locs.extend(link_zero_locs);
             }
-            if since_last_change >= links.len() {
-                break;
-            } else {
-                since_last_change += 1;
+        }
+        let grew = !derived.is_empty();
+        for region in derived {
+            if !seen.insert(region.hidden().clone()) {
+                continue;
+            }
+            let partners: IndexSet<usize> = region.hidden().iter()
+                .flat_map(|loc| covering.get(&loc).into_iter().flatten().cloned())
+                .collect();
+            let new_links = partners.into_iter()
+                .filter_map(|partner| LinkedSubRegion::deduce_links(&regions[partner], &region));
+            links.extend(new_links);
+            for loc in region.hidden().iter() {
+                covering.entry(loc).or_default().push(regions.len());
             }
+            regions.push(region);
+        }
+        if !pending.empty.is_disjoint(&found.mines) || !pending.mines.is_disjoint(&found.empty) {
+            return Err(());
         }
+        pending.empty = pending.empty.difference(&found.empty);
+        pending.mines = pending.mines.difference(&found.mines);
+        if pending.is_empty() && !grew {
+            return Ok(Solution {
+                known: found.into_known_cells(),
+                probabilities: None,
+                complete: true,
+            });
+        }
+    }
+}
+
+// The whole point of this struct.
+impl<'a> Solver<'a> {
+    // Error when board state contradicts itself. Typically due to error in placing a flagged cell.
+    pub fn solve(&mut self) -> Result<Solution, ()> {
+        let constraints = Constraints {
+            width: self.board.w(),
+            capacity: self.board.capacity() as usize,
+            regions: self.valid_regions.drain(..).collect(),
+            board: self.board_region.clone(),
+        };
+        self.backend.get().solve(&constraints, &self.budget.start())
+    }
+
+    // Only what could be proven within the budget, whether or not the solve got to the end.
+    pub fn calculate_known_cells(&mut self) -> Result<Option<KnownCells>, ()> {
+        let known = self.solve()?.known;
         // TODO There will be 3 categories of spots: unknown, is_mine, is_empty.
-        // unimplemented!("Solver not yet fully functional.");
-        if self.found_empty_locs.is_empty() && self.found_mine_locs.is_empty() {
+        if known.is_empty() {
             Ok(None)
         } else {
-            Ok(Some(KnownCells {
-                empty: self.found_empty_locs.clone(),
-                mines: self.found_mine_locs.clone(),
-            }))
+            Ok(Some(known))
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::path::Path;
+    use indexmap::{IndexMap, IndexSet};
+
+    use crate::board::{Board, CellState};
+    use super::{mine_probabilities, Backend, Solver};
+
+    type Loc = (usize, usize);
+    // Name, board, safe cells and mines.
+    type Pattern = (&'static str, &'static [u8], &'static [Loc], &'static [Loc]);
+
+    // A board from the corpus, along with everything that can be known about it.
+    struct Position {
+        board: Board,
+        safe: IndexSet<Loc>,
+        mines: IndexSet<Loc>,
+        probabilities: IndexMap<Loc, f64>,
+        // Backends that are allowed to leave some of the safe cells and mines unknown.
+        incomplete: Vec<Backend>,
+    }
+
+    // The board in the save format, then a blank line, then one annotation per line:
+    // - `safe x,y ...` and `mines x,y ...` for every cell that can be known
+    // - `probability p x,y ...` for the exact chance of a mine, as a fraction or a decimal
+    // - `incomplete backend` for a backend that doesn't find everything
+    // Lines starting with `#` are left out.
+    fn parse_position(text: &str) -> Result<Position, String> {
+        let text = text.replace("\r\n", "\n");
+        let (board, annotations) = match text.find("\n\n") {
+            Some(i) => (&text[..=i], &text[i + 2..]),
+            None => return Err("no blank line after the board".to_owned()),
+        };
+        let board = Board::from_save(board.as_bytes()).map_err(|e| e.to_string())?;
+        let loc = |word: &str| -> Result<Loc, String> {
+            let mut parts = word.splitn(2, ',').map(str::parse);
+            match (parts.next(), parts.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+                _ => Err(format!("`{}` isn't a location", word)),
+            }
+        };
+        let probability = |word: &str| -> Result<f64, String> {
+            let mut parts = word.splitn(2, '/').map(str::parse::<f64>);
+            match (parts.next(), parts.next()) {
+                (Some(Ok(p)), None) => Ok(p),
+                (Some(Ok(n)), Some(Ok(d))) => Ok(n / d),
+                _ => Err(format!("`{}` isn't a probability", word)),
+            }
+        };
+        let mut position = Position {
+            board,
+            safe: IndexSet::new(),
+            mines: IndexSet::new(),
+            probabilities: IndexMap::new(),
+            incomplete: vec![],
+        };
+        for line in annotations.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("safe") => for word in words {
+                    position.safe.insert(loc(word)?);
+                },
+                Some("mines") => for word in words {
+                    position.mines.insert(loc(word)?);
+                },
+                Some("probability") => {
+                    let p = probability(words.next().unwrap_or_default())?;
+                    for word in words {
+                        position.probabilities.insert(loc(word)?, p);
+                    }
+                },
+                Some("incomplete") => for word in words {
+                    position.incomplete.push(word.parse().map_err(|e| format!("{}", e))?);
+                },
+                _ => return Err(format!("`{}` isn't an annotation", line)),
+            }
+        }
+        Ok(position)
+    }
+
+    // Everything that doesn't match up with what the position says, a cell at a time.
+    fn check_position(position: &Position) -> Vec<String> {
+        let board = &position.board;
+        let hidden: Vec<_> = board.all_locs()
+            .filter(|(x, y)| match board.cells[*y][*x].state {
+                CellState::Hidden | CellState::Unsure => true,
+                CellState::Visible | CellState::Marked(_) => false,
+            })
+            .collect();
+        let mut mismatches = vec![];
+        let status = |safe: &IndexSet<Loc>, mines: &IndexSet<Loc>, loc: &Loc| {
+            if safe.contains(loc) {
+                "safe"
+            } else if mines.contains(loc) {
+                "a mine"
+            } else {
+                "unknown"
+            }
+        };
+        for loc @ (x, y) in &hidden {
+            let expected = status(&position.safe, &position.mines, loc);
+            let actual = if board.cells[*y][*x].is_mine() { "a mine" } else { "safe" };
+            if expected != "unknown" && expected != actual {
+                mismatches.push(format!("{:?} is annotated as {}, but is {} on the board", loc, expected, actual));
+            }
+        }
+        let compare_probabilities = |mismatches: &mut Vec<String>, name: &str, probabilities: &IndexMap<Loc, f64>| {
+            for (loc, expected) in &position.probabilities {
+                let chance = format!("{}: {:?} should have a {} chance of being a mine", name, loc, expected);
+                match probabilities.get(loc) {
+                    Some(p) if (p - expected).abs() < 1e-9 => {},
+                    Some(p) => mismatches.push(format!("{}, not {}", chance, p)),
+                    None => mismatches.push(format!("{}, but has none", chance)),
+                }
+            }
+        };
+        match mine_probabilities(board) {
+            Some(probabilities) => compare_probabilities(&mut mismatches, "enumeration", &probabilities),
+            None if !position.probabilities.is_empty() => mismatches.push("enumeration: no probabilities".to_owned()),
+            None => {},
+        }
+
+        for backend in [Backend::Linking, Backend::Sat].iter().cloned() {
+            let solution = match Solver::with_backend(board, backend).solve() {
+                Ok(solution) => solution,
+                Err(()) => {
+                    mismatches.push(format!("{}: the board contradicts itself", backend));
+                    continue;
+                },
+            };
+            for loc in &hidden {
+                let expected = status(&position.safe, &position.mines, loc);
+                let actual = status(&solution.known.empty, &solution.known.mines, loc);
+                let missed = actual == "unknown" && position.incomplete.contains(&backend);
+                if expected != actual && !missed {
+                    mismatches.push(format!("{}: {:?} should be {}, but is {}", backend, loc, expected, actual));
+                }
+            }
+            if let Some(probabilities) = &solution.probabilities {
+                compare_probabilities(&mut mismatches, &backend.to_string(), probabilities);
+            }
+        }
+        mismatches
+    }
+
+    // Runs every backend over every position in the corpus, and reports all of the mismatches at
+    // once.
     #[test]
     fn solver_test() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testing/boards/positions");
+        let mut paths: Vec<_> = std::fs::read_dir(&dir)
+            .expect("corpus to be readable.")
+            .map(|entry| entry.expect("corpus to be readable.").path())
+            .filter(|path| path.extension() == Some("txt".as_ref()))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "corpus to have positions in it.");
+        let mut mismatches = vec![];
+        for path in &paths {
+            let name = path.file_name().expect("path to be a file.").to_string_lossy();
+            let text = std::fs::read_to_string(path).expect("position to be readable.");
+            match parse_position(&text) {
+                Ok(position) => mismatches.extend(check_position(&position).into_iter().map(|m| format!("{}: {}", name, m))),
+                Err(e) => mismatches.push(format!("{}: {}", name, e)),
+            }
+        }
+        assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+    }
+
+    // Textbook patterns along the edge of the board, with the row of numbers below the cells they
+    // give away. Each is solved completely by every backend.
+    #[test]
+    fn patterns_test() {
+        let patterns: &[Pattern] = &[
+            ("1-1", b"xHHx\n    \n", &[(1, 0), (2, 0)], &[(0, 0), (3, 0)]),
+            ("1-2", b"HxHx\nH   \n", &[(0, 0), (2, 0), (0, 1)], &[(1, 0), (3, 0)]),
+            ("1-2-1", b"HxHxH\nH   H\n", &[(0, 0), (2, 0), (4, 0), (0, 1), (4, 1)], &[(1, 0), (3, 0)]),
+            (
+                "1-2-2-1",
+                b"HHxxHH\nH    H\n",
+                &[(0, 0), (1, 0), (4, 0), (5, 0), (0, 1), (5, 1)],
+                &[(2, 0), (3, 0)],
+            ),
+            // The 2 at (1, 1) covers the cells of both 1s, which only share the corner. Both of
+            // them need their mine outside of it for the 2 to be satisfied, which takes all three
+            // numbers at once.
+            ("shared corner", b"H1x2HH\n12HHxH\nxH1HHH\nxHHxHx\n", &[(0, 0)], &[]),
+        ];
+        for (name, save, empty, mines) in patterns {
+            let board = Board::from_save(save).expect("board to parse correctly.");
+            for backend in [Backend::Linking, Backend::Sat].iter().cloned() {
+                let known = Solver::with_backend(&board, backend).solve()
+                    .expect("board to be consistent.")
+                    .known;
+                assert_eq!(known.empty, empty.iter().cloned().collect::<IndexSet<_>>(), "{} with {}", name, backend);
+                assert_eq!(known.mines, mines.iter().cloned().collect::<IndexSet<_>>(), "{} with {}", name, backend);
+            }
+        }
     }
 }
@@ -0,0 +1,256 @@
+//! Different ways of working out what the board gives away. Every backend gets the same
+//! constraints, taken from the regions around the revealed numbers and the number of mines left on
+//! the board, so that they can be swapped for one another and checked against each other.
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    solver::{
+        budget::Allowance,
+        deduce,
+        region::{LinkedSubRegion, Region},
+        sat::Sat,
+        KnownCells,
+    },
+};
+
+#[derive(Debug)]
+pub struct BackendDoesNotExist;
+
+impl std::fmt::Display for BackendDoesNotExist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Requested solver does not exist. Pick either linking or sat.")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Constraints {
+    // Width of the board, which every set of cells is indexed by.
+    pub width: usize,
+    pub capacity: usize,
+    // One for every revealed number.
+    pub regions: Vec<Region>,
+    // Every hidden cell on the board, and the number of mines left among them.
+    pub board: Region,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Solution {
+    pub known: KnownCells,
+    // The chance that each hidden cell holds at least one mine, for backends that work it out.
+    pub probabilities: Option<IndexMap<(usize, usize), f64>>,
+    // False if the budget ran out before everything that could be proven was. What is known is
+    // still certain, but there may be more to it.
+    pub complete: bool,
+}
+
+pub trait SolverBackend {
+    // Stops early once the `allowance` runs out. Errors when the constraints contradict each other,
+    // which can only happen if a flag is wrong.
+    fn solve(&self, constraints: &Constraints, allowance: &Allowance) -> Result<Solution, ()>;
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Backend {
+    #[default]
+    Linking,
+    Sat,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = BackendDoesNotExist;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linking" => Ok(Self::Linking),
+            "sat" => Ok(Self::Sat),
+            _ => Err(BackendDoesNotExist),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linking => write!(f, "linking"),
+            Self::Sat => write!(f, "sat"),
+        }
+    }
+}
+
+impl Backend {
+    pub fn get(self) -> &'static dyn SolverBackend {
+        match self {
+            Self::Linking => &Linking,
+            Self::Sat => &Sat,
+        }
+    }
+}
+
+// Links every pair of overlapping regions, and draws whatever conclusions the regions and links
+// allow on their own. Quick, but it doesn't see everything, and it ignores the mine count.
+pub struct Linking;
+
+impl SolverBackend for Linking {
+    fn solve(&self, constraints: &Constraints, allowance: &Allowance) -> Result<Solution, ()> {
+        let regions = constraints.regions.clone();
+        // Only regions that share a hidden cell can be linked, so pairs are found through the
+        // regions covering each cell, rather than by trying every pair of regions.
+        let mut covering: IndexMap<(usize, usize), Vec<usize>> = IndexMap::new();
+        for (i, region) in regions.iter().enumerate() {
+            for loc in region.hidden().iter() {
+                covering.entry(loc).or_default().push(i);
+            }
+        }
+        let pairs = covering.values()
+            .flat_map(|rr| rr.iter().enumerate().flat_map(move |(k, i)| rr[k + 1..].iter().map(move |j| (*i, *j))))
+            .collect::<IndexSet<_>>();
+        let links = pairs.into_iter()
+            .filter_map(|(i, j)| LinkedSubRegion::deduce_links(&regions[i], &regions[j]))
+            .collect::<Vec<_>>();
+        deduce(constraints.width, regions, links, allowance)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    use crate::{
+        board::{Board, CellCategory, CellState, Dim},
+        solver::{mine_probabilities, Budget, Solver},
+    };
+    use super::Backend;
+
+    // Plays random games, and checks every backend against the board, each other, and the exact
+    // probabilities.
+    #[test]
+    fn cross_check_test() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(45);
+        for game in 0..16 {
+            let capacity = if game % 4 == 3 { 2 } else { 1 };
+            let mut board = Board::new_seeded(Dim::Rect(9, 7), 12, capacity, rng.gen())
+                .expect("board to be created without a hitch.");
+            for _ in 0..30 {
+                let linking = Solver::with_backend(&board, Backend::Linking).solve()
+                    .expect("board to be consistent.");
+                let sat = Solver::with_backend(&board, Backend::Sat).solve()
+                    .expect("board to be consistent.");
+                let message = format!("game {}:\n{}", game, board.to_save());
+
+                // Linking only finds what can be proven, and the SAT backend finds all of that.
+                assert!(linking.known.empty.is_subset(&sat.known.empty), "{}", message);
+                assert!(linking.known.mines.is_subset(&sat.known.mines), "{}", message);
+                for (x, y) in &sat.known.empty {
+                    assert!(!board.cells[*y][*x].is_mine(), "{}", message);
+                }
+                for (x, y) in &sat.known.mines {
+                    assert_eq!(board.cells[*y][*x].category, CellCategory::Mine(capacity), "{}", message);
+                }
+
+                let exact = mine_probabilities(&board).expect("board to be simple enough to enumerate.");
+                let probabilities = sat.probabilities.expect("board to be simple enough to enumerate.");
+                assert_eq!(probabilities.len(), exact.len(), "{}", message);
+                for (loc, p) in &exact {
+                    assert!((probabilities[loc] - p).abs() < 1e-9, "{:?} {}", loc, message);
+                    // Cells that can't be mines are always known.
+                    assert_eq!(p.abs() < 1e-9, sat.known.empty.contains(loc), "{:?} {}", loc, message);
+                    if capacity == 1 {
+                        assert_eq!((p - 1.0).abs() < 1e-9, sat.known.mines.contains(loc), "{:?} {}", loc, message);
+                    }
+                }
+
+                let hidden: Vec<_> = board.all_locs()
+                    .filter(|(x, y)| board.cells[*y][*x].state == CellState::Hidden)
+                    .collect();
+                if hidden.iter().all(|(x, y)| board.cells[*y][*x].is_mine()) {
+                    break;
+                }
+                let loc @ (x, y) = hidden[rng.gen_range(0, hidden.len())];
+                // Flags always claim a full cell, so cells that aren't are left alone.
+                if board.cells[y][x].category == CellCategory::Mine(capacity) {
+                    board.flag(loc).expect("location to be on the board.");
+                } else if !board.cells[y][x].is_mine() {
+                    board.dig(loc).expect("location to be safe.");
+                }
+            }
+        }
+    }
+
+    // Whatever else they get wrong, backends never call a mine safe or an empty cell a mine, however
+    // much of a random board is uncovered.
+    #[test]
+    fn safe_cells_test() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(49);
+        for game in 0..60 {
+            let dim = Dim::Rect(rng.gen_range(2, 11), rng.gen_range(2, 11));
+            let capacity = if game % 3 == 2 { 2 } else { 1 };
+            let room = (dim.w() * dim.h()) as u64 * capacity as u64;
+            let mut board = Board::new_seeded(dim, rng.gen_range(1, room / 4 + 2), capacity, rng.gen())
+                .expect("board to be created without a hitch.");
+            let uncovered = rng.gen_range(0, 4);
+            for (x, y) in board.all_locs().collect::<Vec<_>>() {
+                let cell = board.cells[y][x];
+                if rng.gen_range(0, 4) >= uncovered {
+                    continue;
+                }
+                if cell.category == CellCategory::Mine(capacity) {
+                    board.flag((x, y)).expect("location to be on the board.");
+                } else if !cell.is_mine() && cell.state == CellState::Hidden {
+                    board.dig((x, y)).expect("location to be safe.");
+                }
+            }
+            for backend in [Backend::Linking, Backend::Sat].iter().cloned() {
+                let solution = Solver::with_backend(&board, backend).solve()
+                    .expect("board to be consistent.");
+                let message = format!("game {} {}:\n{}", game, backend, board.to_save());
+                for (x, y) in &solution.known.empty {
+                    assert!(!board.cells[*y][*x].is_mine(), "{:?} {}", (x, y), message);
+                }
+                for (x, y) in &solution.known.mines {
+                    assert_eq!(board.cells[*y][*x].category, CellCategory::Mine(capacity), "{:?} {}", (x, y), message);
+                }
+            }
+        }
+    }
+
+    // Solves with smaller and smaller budgets only ever find less, and say so.
+    #[test]
+    fn budget_test() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(46);
+        for game in 0..6 {
+            let mut board = Board::new_seeded(Dim::Rect(8, 6), 9, 1, rng.gen())
+                .expect("board to be created without a hitch.");
+            for _ in 0..20 {
+                for backend in [Backend::Linking, Backend::Sat].iter().cloned() {
+                    let full = Solver::with_backend(&board, backend).solve()
+                        .expect("board to be consistent.");
+                    assert!(full.complete);
+                    for nodes in [0, 1, 4, 16, 64].iter().cloned() {
+                        let mut solver = Solver::with_backend(&board, backend);
+                        solver.set_budget(Budget {
+                            time: None,
+                            nodes: Some(nodes),
+                        });
+                        let partial = solver.solve().expect("board to be consistent.");
+                        let message = format!("game {} {} {}:\n{}", game, backend, nodes, board.to_save());
+                        assert!(partial.known.empty.is_subset(&full.known.empty), "{}", message);
+                        assert!(partial.known.mines.is_subset(&full.known.mines), "{}", message);
+                        if partial.complete {
+                            assert_eq!(partial.known, full.known, "{}", message);
+                        }
+                    }
+                }
+
+                let hidden: Vec<_> = board.all_locs()
+                    .filter(|(x, y)| board.cells[*y][*x].state == CellState::Hidden && !board.cells[*y][*x].is_mine())
+                    .collect();
+                if hidden.is_empty() {
+                    break;
+                }
+                board.dig(hidden[rng.gen_range(0, hidden.len())]).expect("location to be safe.");
+            }
+        }
+    }
+}
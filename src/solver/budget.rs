@@ -0,0 +1,62 @@
+//! Limits on how much work a solve may do. The consistency problem is NP complete, so a board can
+//! always be found that takes too long, and a solver that runs out of budget stops and hands back
+//! whatever it had already proven instead.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Budget {
+    // Wall clock time for a whole solve.
+    pub time: Option<Duration>,
+    // Steps for a whole solve. Each backend counts its own kind of step: a round of deductions for
+    // linking, and a search node for SAT.
+    pub nodes: Option<u64>,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn start(&self) -> Allowance {
+        Allowance {
+            deadline: self.time.map(|time| Instant::now() + time),
+            nodes: self.nodes,
+            spent: AtomicU64::new(0),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+}
+
+// A budget that's being spent. Shared between threads when the work is spread over them.
+#[derive(Debug)]
+pub struct Allowance {
+    deadline: Option<Instant>,
+    nodes: Option<u64>,
+    spent: AtomicU64,
+    // Once it's out, it stays out, even if the clock is never checked again.
+    exhausted: AtomicBool,
+}
+
+impl Allowance {
+    // Takes a step out of the budget. False if there was nothing left to take it from.
+    pub fn spend(&self) -> bool {
+        if self.exhausted.load(Ordering::Relaxed) {
+            return false;
+        }
+        let spent = self.spent.fetch_add(1, Ordering::Relaxed) + 1;
+        let over = matches!(self.nodes, Some(nodes) if spent > nodes)
+            || matches!(self.deadline, Some(deadline) if Instant::now() >= deadline);
+        if over {
+            self.exhausted.store(true, Ordering::Relaxed);
+        }
+        !over
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
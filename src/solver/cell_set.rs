@@ -0,0 +1,238 @@
+//! Sets of cells, kept as bits indexed row by row. Regions only ever cover a few rows of the board,
+//! so only the words between the first and last cell in the set are stored, which keeps them small
+//! on wide boards while still letting set operations work a whole word at a time.
+
+type Loc = (usize, usize);
+
+const BITS: usize = 64;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct CellSet {
+    // Width of the board the cells are on, to turn locations into indices and back.
+    width: usize,
+    // Index of the first stored word. Words before it, and after the last stored one, are empty.
+    start: usize,
+    // Never starts or ends with an empty word, so that equal sets are stored the same way.
+    words: Vec<u64>,
+}
+
+// Construction.
+impl CellSet {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            start: 0,
+            words: vec![],
+        }
+    }
+
+    fn from_words(width: usize, start: usize, words: Vec<u64>) -> Self {
+        let mut set = Self {
+            width,
+            start,
+            words,
+        };
+        set.trim();
+        set
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+        let leading = self.words.iter().take_while(|w| **w == 0).count();
+        if leading != 0 {
+            self.words.drain(..leading);
+            self.start += leading;
+        }
+        if self.words.is_empty() {
+            self.start = 0;
+        }
+    }
+}
+
+// Single cells.
+impl CellSet {
+    fn index(&self, (x, y): Loc) -> usize {
+        debug_assert!(x < self.width, "location to be on the board.");
+        y * self.width + x
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.words.len()
+    }
+
+    // The word at index `i`, whether or not it's stored.
+    fn word(&self, i: usize) -> u64 {
+        if self.start <= i && i < self.end() {
+            self.words[i - self.start]
+        } else {
+            0
+        }
+    }
+
+    pub fn insert(&mut self, loc: Loc) -> bool {
+        let index = self.index(loc);
+        let i = index / BITS;
+        if self.words.is_empty() {
+            self.start = i;
+            self.words.push(0);
+        } else if i < self.start {
+            self.words.splice(0..0, std::iter::repeat(0).take(self.start - i));
+            self.start = i;
+        } else if i >= self.end() {
+            self.words.resize(i - self.start + 1, 0);
+        }
+        let word = &mut self.words[i - self.start];
+        let bit = 1 << (index % BITS);
+        let inserted = *word & bit == 0;
+        *word |= bit;
+        inserted
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    // Row by row, as (x, y).
+    pub fn iter(&self) -> impl Iterator<Item = Loc> + '_ {
+        let width = self.width;
+        self.words.iter().enumerate().flat_map(move |(i, word)| {
+            let base = (self.start + i) * BITS;
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let index = base + word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some((index % width, index / width))
+            })
+        })
+    }
+}
+
+impl Extend<Loc> for CellSet {
+    fn extend<I: IntoIterator<Item = Loc>>(&mut self, locs: I) {
+        for loc in locs {
+            self.insert(loc);
+        }
+    }
+}
+
+// Whole sets, a word at a time.
+impl CellSet {
+    pub fn intersection(&self, other: &Self) -> Self {
+        debug_assert_eq!(self.width, other.width, "sets to come from the same board.");
+        let (start, end) = (self.start.max(other.start), self.end().min(other.end()));
+        let words = (start..end.max(start)).map(|i| self.word(i) & other.word(i)).collect();
+        Self::from_words(self.width, start, words)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        debug_assert_eq!(self.width, other.width, "sets to come from the same board.");
+        let words = (self.start..self.end()).map(|i| self.word(i) & !other.word(i)).collect();
+        Self::from_words(self.width, self.start, words)
+    }
+
+    // The cells only in `self`, the cells in both, and the cells only in `other`.
+    pub fn split(&self, other: &Self) -> (Self, Self, Self) {
+        (self.difference(other), self.intersection(other), other.difference(self))
+    }
+
+    // In place, which saves copying sets that keep growing.
+    pub fn union_with(&mut self, other: &Self) {
+        debug_assert_eq!(self.width, other.width, "sets to come from the same board.");
+        if other.is_empty() {
+            return;
+        } else if self.is_empty() {
+            self.clone_from(other);
+            return;
+        }
+        if other.start < self.start {
+            self.words.splice(0..0, std::iter::repeat(0).take(self.start - other.start));
+            self.start = other.start;
+        }
+        if other.end() > self.end() {
+            self.words.resize(other.end() - self.start, 0);
+        }
+        for (i, word) in other.words.iter().enumerate() {
+            self.words[other.start + i - self.start] |= word;
+        }
+    }
+
+    // Same as the length of the intersection, without building it.
+    pub fn intersection_len(&self, other: &Self) -> usize {
+        let (start, end) = (self.start.max(other.start), self.end().min(other.end()));
+        (start..end).map(|i| (self.word(i) & other.word(i)).count_ones() as usize).sum()
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        let (start, end) = (self.start.max(other.start), self.end().min(other.end()));
+        (start..end).all(|i| self.word(i) & other.word(i) == 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indexmap::IndexSet;
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    use super::CellSet;
+
+    // Checks every operation against the same one on hashed sets, for random sets on a board wide
+    // enough that rows span several words.
+    #[test]
+    fn matches_index_set_test() {
+        let (width, height) = (100, 12);
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(43);
+        // Near each other, so that they overlap often.
+        let random_locs = |rng: &mut Xoshiro256PlusPlus, (x, y): (usize, usize)| {
+            let (x, y) = (x + rng.gen_range(0, 3), y + rng.gen_range(0, 3));
+            let n = rng.gen_range(0, 12);
+            (0..n)
+                .map(|_| ((x + rng.gen_range(0, 3)).min(width - 1), (y + rng.gen_range(0, 3)).min(height - 1)))
+                .collect::<IndexSet<_>>()
+        };
+        let set = |locs: &IndexSet<(usize, usize)>| {
+            let mut set = CellSet::new(width);
+            set.extend(locs.iter().cloned());
+            set
+        };
+        for _ in 0..500 {
+            let near = (rng.gen_range(0, width), rng.gen_range(0, height));
+            let (a, b) = (random_locs(&mut rng, near), random_locs(&mut rng, near));
+            let (aa, bb) = (set(&a), set(&b));
+            let sorted = |set: IndexSet<(usize, usize)>| {
+                let mut locs: Vec<_> = set.into_iter().collect();
+                locs.sort_by_key(|(x, y)| (*y, *x));
+                locs
+            };
+            assert_eq!(aa.len(), a.len());
+            assert_eq!(aa.iter().collect::<Vec<_>>(), sorted(a.clone()));
+            assert_eq!(aa.intersection(&bb).iter().collect::<Vec<_>>(), sorted(a.intersection(&b).cloned().collect()));
+            assert_eq!(aa.difference(&bb).iter().collect::<Vec<_>>(), sorted(a.difference(&b).cloned().collect()));
+            let mut union = aa.clone();
+            union.union_with(&bb);
+            assert_eq!(union.iter().collect::<Vec<_>>(), sorted(a.union(&b).cloned().collect()));
+            assert_eq!(aa.is_disjoint(&bb), a.is_disjoint(&b));
+            assert_eq!(aa.intersection_len(&bb), a.intersection(&b).count());
+            // Sets are stored the same way no matter how they were made.
+            assert_eq!(aa.intersection(&bb), set(&a.intersection(&b).cloned().collect()));
+            let mut rejoined = aa.difference(&bb);
+            rejoined.union_with(&aa.intersection(&bb));
+            assert_eq!(rejoined, aa);
+
+            let mut inserted = aa.clone();
+            for loc in &b {
+                assert_eq!(inserted.insert(*loc), !a.contains(loc));
+            }
+            assert_eq!(inserted, union);
+        }
+    }
+}
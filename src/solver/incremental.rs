@@ -0,0 +1,253 @@
+//! A solver that is kept alongside the board for the whole game, instead of being built from
+//! scratch after every move.
+//!
+//! Regions, and the links between them, are only rebuilt around the cells that changed since the
+//! last update, as reported by the board. Regions that are linked together form groups that can be
+//! reasoned about on their own, so only the groups that the changes touched are solved again.
+//! Everything that was known about the rest of the board is kept as it was.
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    board::{Board, Change},
+    solver::{
+        budget::{Allowance, Budget},
+        deduce,
+        region::{LinkedSubRegion, Region},
+        KnownCells,
+    },
+};
+
+type Loc = (usize, usize);
+
+// Links are keyed by both of the numbers whose regions they link, smallest first.
+fn link_key(a: Loc, b: Loc) -> (Loc, Loc) {
+    if (a.1, a.0) < (b.1, b.0) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+#[derive(Debug)]
+pub struct IncrementalSolver {
+    width: usize,
+    // Keyed by the revealed number that each region surrounds.
+    regions: IndexMap<Loc, Region>,
+    links: IndexMap<(Loc, Loc), LinkedSubRegion>,
+    // The numbers that each number's region is linked to.
+    linked: IndexMap<Loc, IndexSet<Loc>>,
+    known: KnownCells,
+    // Numbers in groups that contradict themselves, which can only happen if a flag is wrong.
+    contradicted: IndexSet<Loc>,
+    budget: Budget,
+    // Numbers in groups that ran out of budget before they were solved, to try again next update.
+    incomplete: IndexSet<Loc>,
+}
+
+impl IncrementalSolver {
+    pub fn new(board: &Board) -> Self {
+        Self::with_budget(board, Budget::unlimited())
+    }
+
+    // The budget is spent afresh on every update, starting with the board as it is now.
+    pub fn with_budget(board: &Board, budget: Budget) -> Self {
+        let mut solver = Self {
+            width: board.w(),
+            regions: IndexMap::new(),
+            links: IndexMap::new(),
+            linked: IndexMap::new(),
+            known: KnownCells::default(),
+            contradicted: IndexSet::new(),
+            budget,
+            incomplete: IndexSet::new(),
+        };
+        solver.rebuild(board, board.all_locs().collect());
+        solver
+    }
+
+    // Same as `Solver::calculate_known_cells`, but without any work.
+    pub fn known_cells(&self) -> Result<Option<&KnownCells>, ()> {
+        if !self.contradicted.is_empty() {
+            Err(())
+        } else if self.known.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(&self.known))
+        }
+    }
+
+    // Whether everything that can be known about the board is, or the budget ran out before then.
+    pub fn is_complete(&self) -> bool {
+        self.incomplete.is_empty()
+    }
+
+    // Catches up with the changes made to the board since the last update, as taken from it.
+    pub fn update(&mut self, board: &Board, changes: &[Change]) {
+        if changes.is_empty() && self.incomplete.is_empty() {
+            return;
+        }
+        // A change can only affect the region around the cell itself, if it became a number, and
+        // the regions of the numbers around it.
+        let touched = changes.iter()
+            .flat_map(|(loc, _)| Some(*loc).into_iter().chain(board.surroundings_of(*loc)))
+            .collect();
+        self.rebuild(board, touched);
+    }
+
+    fn rebuild(&mut self, board: &Board, touched: IndexSet<Loc>) {
+        // Everything worked out from the groups being changed has to be worked out again, including
+        // the parts of them that weren't touched, since they may have been split off. Groups that
+        // ran out of budget last time are tried again, even if nothing changed in them.
+        let unfinished: Vec<_> = self.incomplete.drain(..).collect();
+        let stale = self.groups_of(touched.iter().cloned().chain(unfinished));
+        for number in &stale {
+            for loc in self.regions[number].hidden().iter() {
+                self.known.empty.remove(&loc);
+                self.known.mines.remove(&loc);
+            }
+            self.contradicted.remove(number);
+        }
+
+        for number in &touched {
+            if let Some(partners) = self.linked.remove(number) {
+                for partner in partners {
+                    self.links.remove(&link_key(*number, partner));
+                    if let Some(partner_links) = self.linked.get_mut(&partner) {
+                        partner_links.remove(number);
+                    }
+                }
+            }
+            self.regions.remove(number);
+            if let Some(region) = Region::around(board, *number) {
+                self.regions.insert(*number, region);
+            }
+        }
+
+        // Regions can only overlap if their numbers are at most two cells apart.
+        for number @ (x, y) in touched.iter().cloned() {
+            if !self.regions.contains_key(&number) {
+                continue;
+            }
+            let partners = (y.saturating_sub(2)..=y + 2)
+                .flat_map(|py| (x.saturating_sub(2)..=x + 2).map(move |px| (px, py)))
+                .filter(|partner| *partner != number && self.regions.contains_key(partner))
+                .collect::<Vec<_>>();
+            for partner in partners {
+                let key = link_key(number, partner);
+                if self.links.contains_key(&key) {
+                    continue;
+                }
+                let (a, b) = (&self.regions[&key.0], &self.regions[&key.1]);
+                if let Some(link) = LinkedSubRegion::deduce_links(a, b) {
+                    self.links.insert(key, link);
+                    self.linked.entry(key.0).or_default().insert(key.1);
+                    self.linked.entry(key.1).or_default().insert(key.0);
+                }
+            }
+        }
+
+        let dirty = self.groups_of(stale.into_iter().chain(touched));
+        let allowance = self.budget.start();
+        let mut solved = IndexSet::new();
+        for number in &dirty {
+            if solved.contains(number) {
+                continue;
+            }
+            let group = self.groups_of(Some(*number));
+            self.solve(&group, &allowance);
+            solved.extend(group);
+        }
+    }
+
+    // Every number whose region is linked to one of `numbers`, directly or not.
+    fn groups_of(&self, numbers: impl IntoIterator<Item = Loc>) -> IndexSet<Loc> {
+        let mut group: IndexSet<_> = numbers.into_iter()
+            .filter(|number| self.regions.contains_key(number))
+            .collect();
+        let mut i = 0;
+        while let Some(number) = group.get_index(i).cloned() {
+            if let Some(partners) = self.linked.get(&number) {
+                group.extend(partners.iter().cloned());
+            }
+            i += 1;
+        }
+        group
+    }
+
+    fn solve(&mut self, group: &IndexSet<Loc>, allowance: &Allowance) {
+        let regions = group.iter().map(|number| self.regions[number].clone()).collect();
+        let links = group.iter()
+            .flat_map(|number| {
+                let partners = self.linked.get(number).into_iter().flatten();
+                partners.map(move |partner| link_key(*number, *partner))
+            })
+            .filter(|(a, _)| group.contains(a))
+            .collect::<IndexSet<_>>()
+            .into_iter()
+            .map(|key| self.links[&key].clone())
+            .collect();
+        match deduce(self.width, regions, links, allowance) {
+            Ok(solution) => {
+                self.known.empty.extend(solution.known.empty);
+                self.known.mines.extend(solution.known.mines);
+                if !solution.complete {
+                    self.incomplete.extend(group.iter().cloned());
+                }
+            },
+            Err(()) => self.contradicted.extend(group.iter().cloned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    use crate::{
+        board::{Board, CellState, Dim},
+        solver::Solver,
+    };
+    use super::IncrementalSolver;
+
+    // Plays random games, digging safe cells and flagging (and sometimes unflagging) mines, and
+    // checks the solver against a fresh one after every move.
+    #[test]
+    fn matches_from_scratch_test() {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(41);
+        for game in 0..20 {
+            let capacity = if game % 4 == 3 { 2 } else { 1 };
+            let seed = rng.gen();
+            let mut board = Board::new_seeded(Dim::Rect(10, 8), 16, capacity, seed)
+                .expect("board to be created without a hitch.");
+            let mut solver = IncrementalSolver::new(&board);
+            for _ in 0..40 {
+                let hidden: Vec<_> = board.all_locs()
+                    .filter(|(x, y)| board.cells[*y][*x].state != CellState::Visible)
+                    .collect();
+                if hidden.iter().all(|(x, y)| board.cells[*y][*x].is_mine()) {
+                    break;
+                }
+                let loc @ (x, y) = hidden[rng.gen_range(0, hidden.len())];
+                let cell = board.cells[y][x];
+                if cell.is_mine() || cell.is_marked() {
+                    board.mark(loc).expect("location to be on the board.");
+                } else {
+                    board.dig(loc).expect("location to be safe.");
+                }
+                let changes = board.take_changes();
+                solver.update(&board, &changes);
+
+                let expected = Solver::new(&board).calculate_known_cells();
+                assert_eq!(
+                    solver.known_cells().map(|known| known.cloned()),
+                    expected,
+                    "game {}:\n{}",
+                    game,
+                    board.to_save(),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Exact mine probabilities. Every arrangement of mines around the revealed numbers is enumerated,
+//! and weighed by the number of ways the rest of the mines could be spread over the cells that
+//! aren't next to any number.
+//!
+//! Cells are split into components that share no numbers, which are enumerated separately, so the
+//! cost is exponential in the size of the largest component rather than the whole frontier.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    board::{Board, CellState},
+    parallel,
+    solver::region::Region,
+};
+
+// Enumeration gives up after this many steps, across all components, and reports nothing.
+pub(super) const MAX_STEPS: u64 = 1 << 22;
+
+// Constraints are made of the indices of their cells, and the number of mines in them.
+pub(super) type Constraint = (Vec<usize>, usize);
+// The cells of a component, and the constraints on them.
+pub(super) type Piece = (Vec<(usize, usize)>, Vec<Constraint>);
+
+// Cells that share numbers, and how many ways mines can be arranged in them.
+pub(super) struct Component {
+    pub cells: Vec<(usize, usize)>,
+    // Indexed by the number of mines in the component.
+    pub arrangements: Vec<f64>,
+    // Indexed by the number of mines in the component, then by cell. Counts the arrangements in
+    // which the cell holds at least one mine.
+    pub mined: Vec<Vec<f64>>,
+}
+
+struct Search<'a> {
+    capacity: usize,
+    constraints: &'a [Constraint],
+    constraints_of: Vec<Vec<usize>>,
+    assigned: Vec<usize>,
+    sums: Vec<usize>,
+    unassigned: Vec<usize>,
+    // Shared by every component, so that they run out together.
+    steps: &'a AtomicU64,
+    component: Component,
+}
+
+impl<'a> Search<'a> {
+    // Returns `None` if it ran out of steps.
+    fn run(&mut self, i: usize) -> Option<()> {
+        if self.steps.fetch_add(1, Ordering::Relaxed) >= MAX_STEPS {
+            return None;
+        }
+        if i == self.assigned.len() {
+            let mines: usize = self.assigned.iter().sum();
+            let component = &mut self.component;
+            if component.arrangements.len() <= mines {
+                component.arrangements.resize(mines + 1, 0.0);
+                component.mined.resize(mines + 1, vec![0.0; self.assigned.len()]);
+            }
+            component.arrangements[mines] += 1.0;
+            for (cell, n) in self.assigned.iter().enumerate() {
+                if *n != 0 {
+                    component.mined[mines][cell] += 1.0;
+                }
+            }
+            return Some(());
+        }
+        for n in 0..=self.capacity {
+            let capacity = self.capacity;
+            let fits = self.constraints_of[i].iter().all(|c| {
+                let sum = self.sums[*c] + n;
+                let target = self.constraints[*c].1;
+                sum <= target && sum + (self.unassigned[*c] - 1) * capacity >= target
+            });
+            if !fits {
+                continue;
+            }
+            self.assigned[i] = n;
+            for c in &self.constraints_of[i] {
+                self.sums[*c] += n;
+                self.unassigned[*c] -= 1;
+            }
+            let res = self.run(i + 1);
+            for c in &self.constraints_of[i] {
+                self.sums[*c] -= n;
+                self.unassigned[*c] += 1;
+            }
+            res?;
+        }
+        self.assigned[i] = 0;
+        Some(())
+    }
+}
+
+// Components are enumerated on their own, so that they can be spread over threads, but they all
+// take their steps from the same count. `None` once that count passes the limit.
+fn enumerate(
+    capacity: usize,
+    cells: Vec<(usize, usize)>,
+    constraints: &[Constraint],
+    steps: &AtomicU64,
+) -> Option<Component> {
+    let mut constraints_of = vec![vec![]; cells.len()];
+    for (c, (indices, _)) in constraints.iter().enumerate() {
+        for i in indices {
+            constraints_of[*i].push(c);
+        }
+    }
+    let mut search = Search {
+        capacity,
+        constraints,
+        constraints_of,
+        assigned: vec![0; cells.len()],
+        sums: vec![0; constraints.len()],
+        unassigned: constraints.iter().map(|(indices, _)| indices.len()).collect(),
+        steps,
+        component: Component {
+            cells,
+            arrangements: vec![],
+            mined: vec![],
+        },
+    };
+    search.run(0)?;
+    Some(search.component)
+}
+
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    out
+}
+
+// The chance that each hidden cell holds at least one mine. `None` if the board contradicts
+// itself, which can only happen if a flag is wrong, or if the board is too complicated to
+// enumerate.
+pub fn mine_probabilities(board: &Board) -> Option<IndexMap<(usize, usize), f64>> {
+    let capacity = board.capacity() as usize;
+    let regions: Vec<_> = board.all_locs()
+        .filter_map(|loc| Region::around(board, loc))
+        .collect();
+    let hidden: IndexSet<_> = board.all_locs()
+        .filter(|(x, y)| {
+            let state = board.cells[*y][*x].state;
+            state == CellState::Hidden || state == CellState::Unsure
+        })
+        .collect();
+    let remaining = board.num_mines() as i64 - board.num_known_mines() as i64;
+    if remaining < 0 {
+        return None;
+    }
+    let remaining = remaining as usize;
+    let pieces = split_components(&regions);
+
+    // Whichever component takes the step past the limit gives up, but one of them always does, so
+    // the outcome doesn't depend on the order they ran in.
+    let steps = AtomicU64::new(0);
+    let enumerated = parallel::map(pieces, |(cells, constraints)| {
+        enumerate(capacity, cells, &constraints, &steps)
+    });
+    let mut components = vec![];
+    for component in enumerated {
+        let component = component?;
+        if component.arrangements.is_empty() {
+            return None;
+        }
+        components.push(component);
+    }
+    let covered: IndexSet<_> = components.iter().flat_map(|c| c.cells.iter().cloned()).collect();
+    let free: Vec<_> = hidden.into_iter().filter(|loc| !covered.contains(loc)).collect();
+    weigh(&components, &free, remaining, capacity)
+}
+
+// Groups the cells next to numbers into components that share no numbers, in the order they're
+// reached through shared numbers, which keeps searches through them tight.
+pub(super) fn split_components(regions: &[Region]) -> Vec<Piece> {
+    let mut regions_of: IndexMap<(usize, usize), Vec<usize>> = IndexMap::new();
+    for (r, region) in regions.iter().enumerate() {
+        for loc in region.hidden().iter() {
+            regions_of.entry(loc).or_default().push(r);
+        }
+    }
+    let mut seen = IndexSet::new();
+    let mut pieces = vec![];
+    for start in regions_of.keys() {
+        if seen.contains(start) {
+            continue;
+        }
+        let mut cells = IndexSet::new();
+        let mut used_regions = IndexSet::new();
+        cells.insert(*start);
+        let mut i = 0;
+        while let Some(loc) = cells.get_index(i).cloned() {
+            for r in &regions_of[&loc] {
+                if used_regions.insert(*r) {
+                    cells.extend(regions[*r].hidden().iter());
+                }
+            }
+            i += 1;
+        }
+        seen.extend(cells.iter().cloned());
+
+        let constraints: Vec<Constraint> = used_regions.iter()
+            .map(|r| {
+                let region = &regions[*r];
+                let indices = region.hidden().iter()
+                    .map(|loc| cells.get_index_of(&loc).expect("region cells to be in the component."))
+                    .collect::<Vec<_>>();
+                (indices, region.mines())
+            })
+            .collect();
+        pieces.push((cells.into_iter().collect::<Vec<_>>(), constraints));
+    }
+    pieces
+}
+
+// Weighs every arrangement of every component by the number of ways the `remaining` mines could be
+// spread over the `free` cells, which aren't next to any number. `None` if there's no way at all.
+pub(super) fn weigh(
+    components: &[Component],
+    free: &[(usize, usize)],
+    remaining: usize,
+    capacity: usize,
+) -> Option<IndexMap<(usize, usize), f64>> {
+    // Ways to spread some number of mines over the free cells, built up one cell at a time. Rows
+    // are scaled down as they go to stay in range, which only matters when comparing against the
+    // previous row.
+    let mut ways = vec![0.0; remaining + 1];
+    ways[0] = 1.0;
+    let (mut prev_ways, mut scale) = (ways.clone(), 1.0);
+    for _ in 0..free.len() {
+        let mut next = vec![0.0; remaining + 1];
+        for (k, w) in ways.iter().enumerate() {
+            for n in 0..=capacity.min(remaining - k) {
+                next[k + n] += w;
+            }
+        }
+        scale = next.iter().cloned().fold(0.0, f64::max);
+        if scale == 0.0 {
+            return None;
+        }
+        next.iter_mut().for_each(|w| *w /= scale);
+        prev_ways = std::mem::replace(&mut ways, next);
+    }
+    let weight = |mines: usize| if mines <= remaining { ways[remaining - mines] } else { 0.0 };
+
+    let all = components.iter().fold(vec![1.0], |acc, c| convolve(&acc, &c.arrangements));
+    let total: f64 = all.iter().enumerate().map(|(m, n)| n * weight(m)).sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    let mut probabilities = IndexMap::new();
+    for (c, component) in components.iter().enumerate() {
+        let others = components.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != c)
+            .fold(vec![1.0], |acc, (_, other)| convolve(&acc, &other.arrangements));
+        // The weight of every arrangement of this component with a given number of mines, taking
+        // everything else into account.
+        let weights: Vec<f64> = (0..component.arrangements.len())
+            .map(|m| others.iter().enumerate().map(|(o, n)| n * weight(m + o)).sum())
+            .collect();
+        for (i, loc) in component.cells.iter().enumerate() {
+            let mined: f64 = component.mined.iter().zip(&weights).map(|(mined, w)| mined[i] * w).sum();
+            probabilities.insert(*loc, mined / total);
+        }
+    }
+    if !free.is_empty() {
+        let empty: f64 = all.iter()
+            .enumerate()
+            .filter(|(m, _)| *m <= remaining)
+            .map(|(m, n)| n * prev_ways[remaining - m] / scale)
+            .sum();
+        for loc in free {
+            probabilities.insert(*loc, 1.0 - empty / total);
+        }
+    }
+    Some(probabilities)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{board::Board, parallel};
+
+    use super::mine_probabilities;
+
+    #[test]
+    fn probability_test() {
+        let board = Board::from_save(b"x1HH\n").expect("board to parse correctly.");
+        let probabilities = mine_probabilities(&board).expect("board to be consistent.");
+        assert_eq!(probabilities[&(0, 0)], 0.5);
+        assert_eq!(probabilities[&(2, 0)], 0.5);
+        assert_eq!(probabilities[&(3, 0)], 0.0);
+
+        // Nothing revealed, so every cell is as likely as any other.
+        let board = Board::from_save(b"xHHH\nHHxH\n").expect("board to parse correctly.");
+        let probabilities = mine_probabilities(&board).expect("board to be consistent.");
+        assert!(probabilities.values().all(|p| (p - 0.25).abs() < 1e-9));
+
+        // A 1-1 against the wall. The second 1 shares its mine with the first, so the cells only it
+        // can see are safe.
+        let board = Board::from_save(b"HxHH\n11HH\n").expect("board to parse correctly.");
+        let probabilities = mine_probabilities(&board).expect("board to be consistent.");
+        assert_eq!(probabilities[&(0, 0)], 0.5);
+        assert_eq!(probabilities[&(1, 0)], 0.5);
+        assert_eq!(probabilities[&(2, 0)], 0.0);
+        assert_eq!(probabilities[&(2, 1)], 0.0);
+        assert_eq!(probabilities[&(3, 1)], 0.0);
+
+        // Components are enumerated separately, possibly on different threads, which mustn't change
+        // anything.
+        let board = Board::from_save(b"x1HHHH1x\nHHHHHHHH\nHHx21HHH\n").expect("board to parse correctly.");
+        let probabilities = mine_probabilities(&board).expect("board to be consistent.");
+        for threads in 1..=3 {
+            let spread = parallel::with_threads(Some(threads), || mine_probabilities(&board))
+                .expect("thread pool to be built.")
+                .expect("board to be consistent.");
+            assert_eq!(spread, probabilities);
+        }
+    }
+}
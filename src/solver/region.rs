@@ -7,22 +7,25 @@ use crate::{
         CellCategory,
         Board,
     },
-    util::split_sets,
+    solver::CellSet,
 };
 
 // Regions have definite number of bombs.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Region {
     // Each bound is "or"d with the others.
     mines: usize,
-    hidden: IndexSet<(usize, usize)>,
+    // Maximum number of mines in each hidden cell.
+    capacity: usize,
+    hidden: CellSet,
 }
 
 // Construction.
 impl Region {
-    pub fn new(mines: usize, hidden: IndexSet<(usize, usize)>) -> Self {
+    pub fn new(mines: usize, capacity: usize, hidden: CellSet) -> Self {
         Self {
             mines,
+            capacity,
             hidden,
         }
     }
@@ -31,7 +34,7 @@ impl Region {
         let (col, row) = sentinel_loc;
         let sentinel = &board.cells[row][col];
         // Hidden and empty (with no surrounding mines) means no known mines nearby, and therefore
-        // have no region. Marked cells are also useless.
+        // have no region. Marked cells and exploded mines are also useless.
         if sentinel.state != CellState::Visible {
             return None;
         }
@@ -43,150 +46,124 @@ impl Region {
             // A hidden or marked cell contributes no information to its surrounding region.
             // Neither does a revealed cell that is empty.
             (CellState::Hidden, _) => return None,
-            (CellState::Marked, _) => return None,
+            (CellState::Unsure, _) => return None,
+            (CellState::Marked(_), _) => return None,
             (CellState::Visible, CellCategory::Empty(None)) => return None,
             // Needs further processing, since the cell contributes information.
             (CellState::Visible, CellCategory::Empty(Some(n))) => n,
-            // This is an error state.
-            (CellState::Visible, CellCategory::Mine) => panic!("Did not expect to attempt to solve a board with an exploded mine."),
+            (CellState::Visible, CellCategory::Mine(_)) => return None,
         };
-        let mut hidden = IndexSet::new();
+        let mut hidden = CellSet::new(board.w());
         for watched_loc in board.surroundings_of(sentinel_loc) {
             let watched_cell = board.cells[watched_loc.1][watched_loc.0];
             match watched_cell.state {
-                // Is known, and therefore not part of the region.
-                CellState::Visible => (),
-                // Is presumed known, and therefore not part of the region.
-                CellState::Marked => {
-                    // TODO this assertion can fail if a mistake is made.
-                    assert!(num_watched_mines != 0);
-                    num_watched_mines -= 1;
+                // Is known, and therefore not part of the region. Exploded mines are known mines, and
+                // can still end up being one too many when a flag next to them is wrong.
+                CellState::Visible => {
+                    num_watched_mines = num_watched_mines.checked_sub(watched_cell.known_mines())?;
                 },
-                // Is unknown, and therefore required in analysis
-                CellState::Hidden => {
+                // Is presumed known, and therefore not part of the region. A number with more flags
+                // around it than it has mines has a wrong flag somewhere, and can't be trusted.
+                CellState::Marked(n) => {
+                    num_watched_mines = num_watched_mines.checked_sub(n)?;
+                },
+                // Is unknown, and therefore required in analysis. Unsure marks are not flags, so
+                // nothing is subtracted for them.
+                CellState::Hidden | CellState::Unsure => {
                     hidden.insert(watched_loc);
                 },
             };
         }
 
-        Some(Region::new(num_watched_mines as usize, hidden))
+        Some(Region::new(num_watched_mines as usize, board.capacity() as usize, hidden))
     }
 
     pub fn board(board: &Board) -> Region {
         let mut num_mines: usize = 0;
         let mut num_flagged: usize = 0;
-        let mut hidden = IndexSet::new();
+        let mut hidden = CellSet::new(board.w());
         for loc in board.all_locs() {
-            let (row, col) = loc;
+            let (col, row) = loc;
             let cell = &board.cells[row][col];
-            if cell.category == CellCategory::Mine {
-                num_mines += 1;
+            if let CellCategory::Mine(n) = cell.category {
+                num_mines += n as usize;
             }
             match cell {
-                // Is known. Contributes no information.
-                Cell { state: CellState::Visible, .. } => {},
+                // Is known. Contributes no information, unless it's an exploded mine.
+                Cell { state: CellState::Visible, .. } => {
+                    num_flagged += cell.known_mines() as usize;
+                },
                 // Is presumed to be a mine, so detracted from original num_mines.
-                Cell { state: CellState::Marked, .. } => {
-                    num_flagged += 1;
+                Cell { state: CellState::Marked(n), .. } => {
+                    num_flagged += *n as usize;
                 },
                 // Is unknown, and location is therefore required in analysis.
-                Cell { state: CellState::Hidden, .. } => {
+                Cell { state: CellState::Hidden, .. } | Cell { state: CellState::Unsure, .. } => {
                     hidden.insert(loc);
                 },
             };
         }
 
-        assert!(num_flagged < num_mines, "flagged cells to be less than mined cells.");
-        Self::new(num_mines - num_flagged, hidden)
+        // More flags than mines means some of them are wrong, and that nothing is left to find.
+        Self::new(num_mines.saturating_sub(num_flagged), board.capacity() as usize, hidden)
     }
 }
 
 // Stat calculation.
 impl Region {
+    pub fn mines(&self) -> usize {
+        self.mines
+    }
+
+    pub fn hidden(&self) -> &CellSet {
+        &self.hidden
+    }
+
     pub fn is_all_mines(&self) -> bool {
-        self.hidden.len() == self.mines
+        self.hidden.len() * self.capacity == self.mines
     }
 
     pub fn is_all_empty(&self) -> bool {
         self.mines == 0
     }
+
+    // Whether the mines still fit in the hidden cells.
+    pub fn is_consistent(&self) -> bool {
+        self.mines <= self.hidden.len() * self.capacity
+    }
 }
 
 // Removing locations from an individual region.
 impl Region {
-    fn remove_locs_from_hidden<'a, I: IntoIterator<Item = &'a (usize, usize)>>(&mut self, locs: I) -> usize {
-        let mut num_removed = 0;
-        for remove_loc in locs {
-            if self.hidden.remove(remove_loc) {
-                num_removed += 1;
-            }
+    fn remove_locs_from_hidden(&mut self, locs: &CellSet) -> usize {
+        let num_removed = self.hidden.intersection_len(locs);
+        if num_removed != 0 {
+            self.hidden = self.hidden.difference(locs);
         }
         num_removed
     }
 
-    pub fn remove_empty_locs<'a, I: IntoIterator<Item = &'a (usize, usize)>>(&mut self, locs: I) {
+    pub fn remove_empty_locs(&mut self, locs: &CellSet) {
         self.remove_locs_from_hidden(locs);
     }
 
-    pub fn remove_mine_locs<'a, I: IntoIterator<Item = &'a (usize, usize)>>(&mut self, locs: I) {
+    // Errors if the region doesn't have room for that many mines.
+    pub fn remove_mine_locs(&mut self, locs: &CellSet) -> Result<(), ()> {
+        // Known mine locations are always full to capacity.
         let num_removed = self.remove_locs_from_hidden(locs);
-        self.mines -= num_removed;
+        self.mines = self.mines.checked_sub(num_removed * self.capacity).ok_or(())?;
+        Ok(())
     }
 }
 
-#[derive(Debug)]
-pub struct StrippedRegions {
-    pub locs: IndexSet<(usize, usize)>,
-    pub regions: Vec<Region>,
-}
-
-// Removing regions that are empty/mined.
-impl Region {
-    pub fn strip_zero_regions_from(rr: Vec<Region>) -> StrippedRegions {
-        let (mut zero, mut nonzero) = (vec![], vec![]);
-        for r in rr {
-            if r.is_all_empty() {
-                zero.push(r)
-            } else {
-                nonzero.push(r)
-            }
-        }
-        let zero_locs = zero.into_iter().flat_map(|r| r.hidden).collect::<IndexSet<_>>();
-        for r in &mut nonzero {
-            r.remove_empty_locs(&zero_locs);
-        }
-        StrippedRegions {
-            locs: zero_locs,
-            regions: nonzero,
-        }
-    }
-
-    pub fn strip_mine_regions_from(rr: Vec<Region>) -> StrippedRegions {
-        let (mut mined, mut partially_unmined) = (vec![], vec![]);
-        for r in rr {
-            if r.is_all_mines() {
-                mined.push(r)
-            } else {
-                partially_unmined.push(r)
-            }
-        }
-        let mined_locs = mined.into_iter().flat_map(|r| r.hidden).collect::<IndexSet<_>>();
-        for r in &mut partially_unmined {
-            r.remove_mine_locs(&mined_locs);
-        }
-        StrippedRegions {
-            locs: mined_locs,
-            regions: partially_unmined,
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LinkedSubRegion {
     pub mine_sets: IndexSet<(usize, usize, usize)>,
-    pub r0: IndexSet<(usize, usize)>,
-    pub rs: IndexSet<(usize, usize)>,
-    pub r1: IndexSet<(usize, usize)>,
+    // Maximum number of mines in each cell, shared by both parents.
+    pub capacity: usize,
+    pub r0: CellSet,
+    pub rs: CellSet,
+    pub r1: CellSet,
 }
 
 // Create LinkedSubRegions from Regions.
@@ -195,9 +172,7 @@ impl LinkedSubRegion {
         parent0: &Region,
         parent1: &Region,
     ) -> Option<LinkedSubRegion> {
-        let (r0_hidden, rs_hidden, r1_hidden) =
-            split_sets(parent0.hidden.clone(), parent1.hidden.clone());
-        // TODO Calculate the constraints on mines, given what is required.
+        let (r0_hidden, rs_hidden, r1_hidden) = parent0.hidden.split(&parent1.hidden);
         let rs_num_hidden = rs_hidden.len();
         let r0_num_hidden = r0_hidden.len();
         let r1_num_hidden = r1_hidden.len();
@@ -207,39 +182,30 @@ impl LinkedSubRegion {
         }
 
         let (p0_mines, p1_mines) = (parent0.mines, parent1.mines);
+        debug_assert_eq!(parent0.capacity, parent1.capacity, "regions to come from the same board.");
+        let capacity = parent0.capacity;
 
-        // The maximum number of shared mines is obviously bounded by three things:
-        // - The number of hidden cells
+        // The most mines the overlap can hold is bounded by three things:
+        // - The number of mines that fit in the overlap
         // - The number of mines present in one parent region
         // - The number of mines present in the other parent region
-        let rs_max_mines = (rs_num_hidden).max(p0_mines).max(p1_mines);
-        // The minimum number of shared mines is obviously bounded by three things:
+        let rs_max_mines = (rs_num_hidden * capacity).min(p0_mines).min(p1_mines);
+        // The fewest is bounded by three things:
         // - 0
         // - The number of mines that don't fit in region 0 of parent 0
         // - The number of mines that don't fit in region 1 of parent 1
-        let rs_min_mines = {
-            let r0_min_contribution = if p0_mines < r0_num_hidden {
-                0
-            } else {
-                p0_mines - r0_num_hidden
-            };
-            let r1_min_contribution = if p1_mines < r1_num_hidden {
-                0
-            } else {
-                p1_mines - r1_num_hidden
-            };
-            r0_min_contribution.max(r1_min_contribution)
-        };
+        // Which also keeps the mines left over for regions 0 and 1 within what fits in them.
+        let rs_min_mines = p0_mines.saturating_sub(r0_num_hidden * capacity)
+            .max(p1_mines.saturating_sub(r1_num_hidden * capacity));
 
-        let mut linkages = IndexSet::new();
-        for rs_mines in rs_min_mines..=rs_max_mines {
-            let r0_mines = p0_mines - rs_mines;
-            let r1_mines = p1_mines - rs_mines;
-            linkages.insert((r0_mines, rs_mines, r1_mines));
-        }
+        // Empty if the parents can't both be satisfied, which the caller notices.
+        let linkages = (rs_min_mines..=rs_max_mines)
+            .map(|rs_mines| (p0_mines - rs_mines, rs_mines, p1_mines - rs_mines))
+            .collect();
 
         Some(LinkedSubRegion {
             mine_sets: linkages,
+            capacity,
             r0: r0_hidden,
             rs: rs_hidden,
             r1: r1_hidden,
@@ -247,26 +213,45 @@ impl LinkedSubRegion {
     }
 }
 
+// Conclusions that every possible split of the mines agrees on.
+impl LinkedSubRegion {
+    pub fn parts(&self) -> [&CellSet; 3] {
+        [&self.r0, &self.rs, &self.r1]
+    }
+
+    // The fewest and most mines each part could hold. Meaningless without any mine sets.
+    pub fn bounds(&self) -> [(usize, usize); 3] {
+        let mut bounds = [(usize::MAX, 0); 3];
+        for (m0, ms, m1) in &self.mine_sets {
+            for (bound, m) in bounds.iter_mut().zip([*m0, *ms, *m1].iter()) {
+                *bound = (bound.0.min(*m), bound.1.max(*m));
+            }
+        }
+        bounds
+    }
+}
+
 // Manipulate locations in the linked regions.
 impl LinkedSubRegion {
-    fn remove_from_r(&mut self, c: u8, locs: &IndexSet<(usize, usize)>) -> usize {
+    fn remove_from_r(&mut self, c: u8, locs: &CellSet) -> usize {
         let r = match c {
             b'0' => &mut self.r0,
             b's' => &mut self.rs,
             b'1' => &mut self.r1,
             _ => panic!("bad region number provided to linked sub region: {}", c),
         };
-        let original_r_size = r.len();
-        r.retain(|l| !locs.contains(l));
-        let removed = original_r_size - r.len();
+        let removed = r.intersection_len(locs);
+        if removed != 0 {
+            *r = r.difference(locs);
+        }
         removed
     }
 
-    pub fn remove_mines(&mut self, locs: &IndexSet<(usize, usize)>) {
-        // Iterate over locations to remove
-        let r0_rem = self.remove_from_r(b'0', locs);
-        let rs_rem = self.remove_from_r(b's', locs);
-        let r1_rem = self.remove_from_r(b'1', locs);
+    pub fn remove_mines(&mut self, locs: &CellSet) {
+        // Iterate over locations to remove. Known mine locations are always full to capacity.
+        let r0_rem = self.remove_from_r(b'0', locs) * self.capacity;
+        let rs_rem = self.remove_from_r(b's', locs) * self.capacity;
+        let r1_rem = self.remove_from_r(b'1', locs) * self.capacity;
         self.mine_sets = self.mine_sets.drain(..)
             .filter(|&(m0, ms, m1)| {
                 m0 >= r0_rem
@@ -281,35 +266,37 @@ impl LinkedSubRegion {
             .collect();
     }
 
-    pub fn remove_empty(&mut self, locs: &IndexSet<(usize, usize)>) {
+    pub fn remove_empty(&mut self, locs: &CellSet) {
         self.remove_from_r(b'0', locs);
         self.remove_from_r(b's', locs);
         self.remove_from_r(b'1', locs);
-        let (r0_len, rs_len, r1_len) = (self.r0.len(), self.rs.len(), self.r1.len());
+        let (r0_len, rs_len, r1_len) = (
+            self.r0.len() * self.capacity,
+            self.rs.len() * self.capacity,
+            self.r1.len() * self.capacity,
+        );
+        // Nothing left means the board contradicts itself, which is left to the caller to notice.
         self.mine_sets.retain(|&(m0, ms, m1)| {
             m0 <= r0_len
                 && ms <= rs_len
                 && m1 <= r1_len
         });
-        // TODO Actually resolve this error, since it's a valid game state.
-        assert!(self.mine_sets.len() != 1, "an empty set should be impossible if marks are correct.");
     }
 }
 
 #[cfg(test)]
 mod test {
-    use indexmap::IndexSet;
-    use crate::board::Board;
+    use crate::{board::Board, solver::CellSet};
 
-    use super::Region;
+    use super::{LinkedSubRegion, Region};
 
     const MINES: usize = 5;
     const LOCS: [(usize, usize); MINES] = [
         (0, 0),
-        (0, 4),
-        (0, 9),
-        (1, 2),
-        (1, 8),
+        (4, 0),
+        (9, 0),
+        (2, 1),
+        (8, 1),
     ];
     fn test_board() -> Board {
         Board::from_save(include_bytes!("../../testing/boards/basic.txt"))
@@ -317,26 +304,91 @@ mod test {
     }
 
     #[test]
-    fn new_test() { // Really?
-        let locs: IndexSet<_> = LOCS.iter().cloned().collect();
-        let r = Region::new(MINES, locs);
+    fn new_test() {
+        let mut locs = CellSet::new(test_board().w());
+        locs.extend(LOCS.iter().cloned());
+        let r = Region::new(MINES, 1, locs.clone());
+        assert_eq!(r.mines(), MINES);
+        assert_eq!(r.hidden(), &locs);
+        assert!(r.is_all_mines());
+        assert!(!r.is_all_empty());
+        assert!(r.is_consistent());
+        // More mines than fit.
+        assert!(!Region::new(MINES + 1, 1, locs).is_consistent());
     }
 
     #[test]
-    fn board_test() { // Really?
-        let locs: IndexSet<_> = LOCS.iter().cloned().collect();
-        let r = Region::new(MINES, locs);
+    fn board_test() {
+        let mut locs = CellSet::new(test_board().w());
+        locs.extend(LOCS.iter().cloned());
+        let r = Region::new(MINES, 1, locs);
         let test = Region::board(&test_board());
 
         assert!(r == test);
     }
 
     #[test]
-    fn surroundings_test() { // Really?
-        let r = Region::new(0, IndexSet::new());
+    fn exploded_mine_test() {
+        // A wrong flag next to an exploded mine leaves the number with too many mines around it.
+        let b = Board::from_save(b"f1!\n").expect("board to parse correctly.");
+        assert_eq!(Region::around(&b, (1, 0)), None);
+    }
 
-        let b = test_board();
-        assert!(true);
+    #[test]
+    fn surroundings_test() {
+        let set = |locs: &[(usize, usize)]| {
+            let mut set = CellSet::new(3);
+            set.extend(locs.iter().cloned());
+            set
+        };
+        let b = Board::from_save(b"x1H\n11H\nFHH\n").expect("board to parse correctly.");
+        assert_eq!(Region::around(&b, (1, 0)), Some(Region::new(1, 1, set(&[(0, 0), (2, 0), (2, 1)]))));
+        // The flag takes care of one of the mines.
+        assert_eq!(
+            Region::around(&b, (1, 1)),
+            Some(Region::new(1, 1, set(&[(0, 0), (2, 0), (2, 1), (1, 2), (2, 2)]))),
+        );
+        // Hidden cells say nothing.
+        assert_eq!(Region::around(&b, (2, 0)), None);
+
+        // Nor do numbers with too many flags around them.
+        let b = Board::from_save(b"1F\nfH\n").expect("board to parse correctly.");
+        assert_eq!(Region::around(&b, (0, 0)), None);
     }
-}
 
+    #[test]
+    fn deduce_links_test() {
+        let set = |locs: &[(usize, usize)]| {
+            let mut set = CellSet::new(10);
+            set.extend(locs.iter().cloned());
+            set
+        };
+        // Two cells can't hold three mines, however many the parents have.
+        let link = LinkedSubRegion::deduce_links(
+            &Region::new(3, 1, set(&[(0, 0), (1, 0), (2, 0), (3, 0)])),
+            &Region::new(3, 1, set(&[(2, 0), (3, 0), (4, 0), (5, 0)])),
+        ).expect("regions to overlap.");
+        assert_eq!(link.mine_sets.iter().cloned().collect::<Vec<_>>(), vec![(2, 1, 2), (1, 2, 1)]);
+
+        // Nor can a single cell hold two, so the overlap has to hold the rest.
+        let link = LinkedSubRegion::deduce_links(
+            &Region::new(3, 1, set(&[(0, 0), (1, 0), (2, 0)])),
+            &Region::new(2, 1, set(&[(1, 0), (2, 0), (3, 0), (4, 0)])),
+        ).expect("regions to overlap.");
+        assert_eq!(link.mine_sets.iter().cloned().collect::<Vec<_>>(), vec![(1, 2, 0)]);
+        assert_eq!(link.bounds(), [(1, 1), (2, 2), (0, 0)]);
+
+        // Up to two mines a cell.
+        let link = LinkedSubRegion::deduce_links(
+            &Region::new(3, 2, set(&[(0, 0), (1, 0)])),
+            &Region::new(1, 2, set(&[(1, 0), (2, 0)])),
+        ).expect("regions to overlap.");
+        assert_eq!(link.mine_sets.iter().cloned().collect::<Vec<_>>(), vec![(2, 1, 0)]);
+
+        // No overlap, no link.
+        assert!(LinkedSubRegion::deduce_links(
+            &Region::new(1, 1, set(&[(0, 0)])),
+            &Region::new(1, 1, set(&[(1, 0)])),
+        ).is_none());
+    }
+}
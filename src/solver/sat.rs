@@ -0,0 +1,515 @@
+//! A backend that treats the board as a satisfiability problem. Every hidden cell next to a number
+//! holds somewhere between none and `capacity` mines, and every number says how many mines are
+//! around it, which makes cardinality constraints rather than plain clauses. The search is a DPLL:
+//! cells are assigned one at a time, and every assignment is followed by propagation, which
+//! narrows down what each cell could still hold until the constraints leave nothing more to
+//! narrow. A conflict backtracks to the last choice.
+//!
+//! A cell is known if no assignment of the whole board gives it anything else. Cells are split
+//! into components that share no numbers, which only have the number of mines left on the board
+//! in common, so each component is searched on its own. Until the mine count rules out some of
+//! the totals the components could hold, which is rare before the end of a game, nothing more is
+//! needed. Otherwise, each component is searched for every total it could hold, and the totals
+//! are matched up against the mine count.
+
+use indexmap::IndexSet;
+
+use crate::{
+    parallel,
+    solver::{
+        backend::{Constraints, Solution, SolverBackend},
+        budget::Allowance,
+        probability::{self, Component, MAX_STEPS},
+        KnownCells,
+    },
+};
+
+// How many mines each cell holds, by index.
+type Model = Vec<usize>;
+// The least and most mines something can hold.
+type Range = (usize, usize);
+
+// The cells of each constraint, as indices, and the least and most mines they can hold between
+// them. The last one covers every cell, and bounds the total.
+struct Problem {
+    capacity: usize,
+    constraints: Vec<(Vec<usize>, usize, usize)>,
+    constraints_of: Vec<Vec<usize>>,
+}
+
+impl Problem {
+    fn new(capacity: usize, cells: usize, constraints: &[probability::Constraint]) -> Self {
+        let mut constraints: Vec<_> = constraints.iter()
+            .map(|(indices, mines)| (indices.clone(), *mines, *mines))
+            .collect();
+        constraints.push(((0..cells).collect(), 0, cells * capacity));
+        let mut constraints_of = vec![vec![]; cells];
+        for (c, (indices, _, _)) in constraints.iter().enumerate() {
+            for i in indices {
+                constraints_of[*i].push(c);
+            }
+        }
+        Self {
+            capacity,
+            constraints,
+            constraints_of,
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.constraints_of.len()
+    }
+
+    fn set_total(&mut self, least: usize, most: usize) {
+        let total = self.constraints.last_mut().expect("total to always be constrained.");
+        total.1 = least;
+        total.2 = most;
+    }
+}
+
+struct Dpll<'a> {
+    problem: &'a Problem,
+    allowance: &'a Allowance,
+    // The range of mines each cell could still hold.
+    least: Vec<usize>,
+    most: Vec<usize>,
+    // Every narrowing, along with the range before it, so that it can be undone.
+    trail: Vec<(usize, usize, usize)>,
+    steps: u64,
+    max_steps: u64,
+}
+
+impl<'a> Dpll<'a> {
+    fn new(problem: &'a Problem, max_steps: u64, allowance: &'a Allowance) -> Self {
+        Self {
+            problem,
+            allowance,
+            least: vec![0; problem.cells()],
+            most: vec![problem.capacity; problem.cells()],
+            trail: vec![],
+            steps: 0,
+            max_steps,
+        }
+    }
+
+    fn narrow(&mut self, cell: usize, least: usize, most: usize) -> bool {
+        if least > most {
+            return false;
+        }
+        if (least, most) != (self.least[cell], self.most[cell]) {
+            self.trail.push((cell, self.least[cell], self.most[cell]));
+            self.least[cell] = least;
+            self.most[cell] = most;
+        }
+        true
+    }
+
+    fn undo(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            let (cell, least, most) = self.trail.pop().expect("trail to be longer than the mark.");
+            self.least[cell] = least;
+            self.most[cell] = most;
+        }
+    }
+
+    // Narrows cells down until the constraints have nothing more to say. False on a conflict.
+    fn propagate(&mut self, mut queue: Vec<usize>) -> bool {
+        let problem = self.problem;
+        let mut queued = vec![false; problem.constraints.len()];
+        for c in &queue {
+            queued[*c] = true;
+        }
+        while let Some(c) = queue.pop() {
+            queued[c] = false;
+            let (cells, least, most) = &problem.constraints[c];
+            // The total usually isn't constrained at all, and covers every cell.
+            if *least == 0 && *most >= cells.len() * problem.capacity {
+                continue;
+            }
+            let floor: usize = cells.iter().map(|i| self.least[*i]).sum();
+            let ceiling: usize = cells.iter().map(|i| self.most[*i]).sum();
+            if floor > *most || ceiling < *least {
+                return false;
+            }
+            for i in cells {
+                let (cell_least, cell_most) = (self.least[*i], self.most[*i]);
+                // Whatever the other cells can't take, or don't leave room for.
+                let narrowed_most = cell_most.min(most - (floor - cell_least));
+                let narrowed_least = cell_least.max(least.saturating_sub(ceiling - cell_most));
+                if (narrowed_least, narrowed_most) == (cell_least, cell_most) {
+                    continue;
+                }
+                if !self.narrow(*i, narrowed_least, narrowed_most) {
+                    return false;
+                }
+                for other in &problem.constraints_of[*i] {
+                    if !queued[*other] {
+                        queued[*other] = true;
+                        queue.push(*other);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    fn start(&mut self) -> bool {
+        self.propagate((0..self.problem.constraints.len()).collect())
+    }
+
+    // Calls `found` with every assignment that satisfies the constraints, until it returns false.
+    // Returns false if it was stopped that way, or `None` if it ran out of steps or budget.
+    fn search(&mut self, found: &mut dyn FnMut(&[usize]) -> bool) -> Option<bool> {
+        self.steps += 1;
+        if self.steps > self.max_steps || !self.allowance.spend() {
+            return None;
+        }
+        let cell = match (0..self.least.len()).find(|i| self.least[*i] < self.most[*i]) {
+            Some(cell) => cell,
+            None => return Some(found(&self.least)),
+        };
+        for n in self.least[cell]..=self.most[cell] {
+            let mark = self.trail.len();
+            if self.narrow(cell, n, n)
+                && self.propagate(self.problem.constraints_of[cell].clone())
+                && !self.search(found)?
+            {
+                self.undo(mark);
+                return Some(false);
+            }
+            self.undo(mark);
+        }
+        Some(true)
+    }
+}
+
+// Any assignment that satisfies the constraints, with `cell` held to the given range if there is
+// one. Errors if the budget ran out before one was found, or shown not to exist.
+fn find(problem: &Problem, cell: Option<(usize, usize, usize)>, allowance: &Allowance) -> Result<Option<Model>, ()> {
+    let mut dpll = Dpll::new(problem, u64::MAX, allowance);
+    if let Some((cell, least, most)) = cell {
+        if !dpll.narrow(cell, least, most) {
+            return Ok(None);
+        }
+    }
+    if !dpll.start() {
+        return Ok(None);
+    }
+    let mut model = None;
+    match dpll.search(&mut |assignment| {
+        model = Some(assignment.to_vec());
+        false
+    }) {
+        Some(_) => Ok(model),
+        None => Err(()),
+    }
+}
+
+// The fewest and most mines the component can hold, along with the assignments that show it.
+// `None` if there's no way to satisfy it at all. If the budget runs out first, the bounds are
+// widened as far as they go, so that they never rule out anything that's possible.
+fn extremes(problem: &mut Problem, allowance: &Allowance) -> Option<(usize, usize, Vec<Model>)> {
+    let full = problem.cells() * problem.capacity;
+    problem.set_total(0, full);
+    let first = match find(problem, None, allowance) {
+        Ok(Some(model)) => model,
+        Ok(None) => return None,
+        Err(()) => return Some((0, full, vec![])),
+    };
+    let mines: usize = first.iter().sum();
+    let (mut fewest, mut most) = (mines, mines);
+    let mut models = vec![first];
+    while fewest > 0 {
+        problem.set_total(0, fewest - 1);
+        match find(problem, None, allowance) {
+            Ok(Some(model)) => {
+                fewest = model.iter().sum();
+                models.push(model);
+            },
+            Ok(None) => break,
+            Err(()) => {
+                fewest = 0;
+                break;
+            },
+        }
+    }
+    while most < full {
+        problem.set_total(most + 1, full);
+        match find(problem, None, allowance) {
+            Ok(Some(model)) => {
+                most = model.iter().sum();
+                models.push(model);
+            },
+            Ok(None) => break,
+            Err(()) => {
+                most = full;
+                break;
+            },
+        }
+    }
+    problem.set_total(0, full);
+    Some((fewest, most, models))
+}
+
+// An assignment for every number of mines between `fewest` and `most` that the component can
+// hold. Totals that the budget ran out on are kept, without one.
+fn totals(problem: &mut Problem, fewest: usize, most: usize, allowance: &Allowance) -> Vec<(usize, Option<Model>)> {
+    let found = (fewest..=most)
+        .filter_map(|total| {
+            problem.set_total(total, total);
+            match find(problem, None, allowance) {
+                Ok(Some(model)) => Some((total, Some(model))),
+                Ok(None) => None,
+                Err(()) => Some((total, None)),
+            }
+        })
+        .collect();
+    problem.set_total(0, problem.cells() * problem.capacity);
+    found
+}
+
+// Whether each cell can hold any mines, and whether it can hold less than a full cell, with the
+// component holding a number of mines in one of the `ranges`. The `models` are already known to
+// fit. Cells that the budget ran out on could go either way.
+fn backbone(
+    problem: &mut Problem,
+    models: &[Model],
+    ranges: &[Range],
+    allowance: &Allowance,
+) -> (Vec<bool>, Vec<bool>) {
+    let capacity = problem.capacity;
+    let (mut can_hold, mut can_lack) = (vec![false; problem.cells()], vec![false; problem.cells()]);
+    let see = |model: &[usize], can_hold: &mut Vec<bool>, can_lack: &mut Vec<bool>| {
+        for (i, n) in model.iter().enumerate() {
+            can_hold[i] |= *n > 0;
+            can_lack[i] |= *n < capacity;
+        }
+    };
+    for model in models {
+        see(model, &mut can_hold, &mut can_lack);
+    }
+    for i in 0..problem.cells() {
+        for (least, most) in ranges {
+            if can_hold[i] {
+                break;
+            }
+            problem.set_total(*least, *most);
+            match find(problem, Some((i, 1, capacity)), allowance) {
+                Ok(Some(model)) => see(&model, &mut can_hold, &mut can_lack),
+                Ok(None) => {},
+                Err(()) => can_hold[i] = true,
+            }
+        }
+        for (least, most) in ranges {
+            if can_lack[i] {
+                break;
+            }
+            problem.set_total(*least, *most);
+            match find(problem, Some((i, 0, capacity - 1)), allowance) {
+                Ok(Some(model)) => see(&model, &mut can_hold, &mut can_lack),
+                Ok(None) => {},
+                Err(()) => can_lack[i] = true,
+            }
+        }
+    }
+    problem.set_total(0, problem.cells() * capacity);
+    (can_hold, can_lack)
+}
+
+// Every sum of something `reached` and something in `set`, up to `most`.
+fn add(reached: &[bool], set: &[bool]) -> Vec<bool> {
+    let mut sums = vec![false; reached.len()];
+    for (a, _) in reached.iter().enumerate().filter(|(_, r)| **r) {
+        for (b, _) in set.iter().enumerate().filter(|(_, s)| **s) {
+            if a + b < sums.len() {
+                sums[a + b] = true;
+            }
+        }
+    }
+    sums
+}
+
+// Every sum of one total from each component, up to `most`.
+fn add_all(possible: &[Vec<bool>], most: usize) -> Vec<bool> {
+    let mut nothing = vec![false; most + 1];
+    nothing[0] = true;
+    possible.iter().fold(nothing, |reached, set| add(&reached, set))
+}
+
+// Which totals each component can hold, given what the others can, for all of them together to
+// hold between `least` and `most` mines. The components before and after each one are summed up
+// separately, so that the others can be matched up without summing them all over again for every
+// component.
+fn allowed_totals(possible: &[Vec<bool>], least: usize, most: usize) -> Vec<Vec<usize>> {
+    let mut nothing = vec![false; most + 1];
+    nothing[0] = true;
+    let mut before = vec![nothing.clone()];
+    for set in possible {
+        let sums = add(before.last().expect("sums to start with nothing."), set);
+        before.push(sums);
+    }
+    let mut after = vec![nothing];
+    for set in possible.iter().rev() {
+        let sums = add(after.last().expect("sums to start with nothing."), set);
+        after.push(sums);
+    }
+    after.reverse();
+    (0..possible.len())
+        .map(|c| {
+            // How many totals the components after this one reach, up to each number of mines.
+            let counts: Vec<usize> = after[c + 1].iter()
+                .scan(0, |count, reached| {
+                    *count += *reached as usize;
+                    Some(*count)
+                })
+                .collect();
+            let reaches_between = |from: usize, to: usize| {
+                from <= to && counts[to] - if from == 0 { 0 } else { counts[from - 1] } > 0
+            };
+            (0..possible[c].len())
+                .filter(|total| possible[c][*total] && *total <= most)
+                .filter(|total| (0..=most - total).any(|b| {
+                    before[c][b] && reaches_between(least.saturating_sub(total + b), most - total - b)
+                }))
+                .collect()
+        })
+        .collect()
+}
+
+pub struct Sat;
+
+impl SolverBackend for Sat {
+    fn solve(&self, constraints: &Constraints, allowance: &Allowance) -> Result<Solution, ()> {
+        let capacity = constraints.capacity;
+        let pieces = probability::split_components(&constraints.regions);
+        let covered: IndexSet<_> = pieces.iter().flat_map(|(cells, _)| cells.iter().cloned()).collect();
+        let free: Vec<_> = constraints.board.hidden().iter().filter(|loc| !covered.contains(loc)).collect();
+        // The components have to hold all of the mines left, apart from what fits in free cells.
+        let remaining = constraints.board.mines();
+        let (least, most) = (remaining.saturating_sub(free.len() * capacity), remaining);
+
+        let mut problems: Vec<_> = pieces.iter()
+            .map(|(cells, component_constraints)| Problem::new(capacity, cells.len(), component_constraints))
+            .collect();
+        let extremes = parallel::map(problems.iter_mut().collect(), |problem| extremes(problem, allowance))
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or(())?;
+        let fewest: usize = extremes.iter().map(|(fewest, _, _)| fewest).sum();
+        let most_held: usize = extremes.iter().map(|(_, most, _)| most).sum();
+
+        // The number of mines each component can hold, and assignments that are known to fit.
+        let (ranges, models, reachable): (Vec<Vec<Range>>, Vec<Vec<Model>>, Vec<usize>) =
+            if (fewest >= least && most_held <= most) || allowance.is_exhausted() {
+                // Nothing the components allow is ruled out by the mine count, which is usually the
+                // case until the very end of the game, so each component can be left to itself. The
+                // same goes for when the budget has run out, since leaving the mine count out only
+                // ever leaves cells unknown.
+                let ranges = extremes.iter().map(|(fewest, most, _)| vec![(*fewest, *most)]).collect();
+                let models = extremes.into_iter().map(|(_, _, models)| models).collect();
+                (ranges, models, vec![fewest, most_held])
+            } else {
+                let work: Vec<_> = problems.iter_mut().zip(&extremes).collect();
+                let found = parallel::map(work, |(problem, (fewest, most, _))| {
+                    totals(problem, *fewest, *most, allowance)
+                });
+                let possible: Vec<Vec<bool>> = found.iter()
+                    .map(|models| {
+                        let mut possible = vec![false; models.last().map_or(0, |(total, _)| total + 1)];
+                        for (total, _) in models {
+                            possible[*total] = true;
+                        }
+                        possible
+                    })
+                    .collect();
+                let allowed = allowed_totals(&possible, least, most);
+                let sums = add_all(&possible, most);
+                let reachable = (least..=most).filter(|total| sums[*total]).collect();
+                let ranges = allowed.iter().map(|totals| totals.iter().map(|total| (*total, *total)).collect()).collect();
+                let models = found.into_iter()
+                    .zip(&allowed)
+                    .map(|(models, allowed)| models.into_iter()
+                        .filter(|(total, _)| allowed.contains(total))
+                        .filter_map(|(_, model)| model)
+                        .collect())
+                    .collect();
+                (ranges, models, reachable)
+            };
+        if reachable.is_empty() || ranges.iter().any(Vec::is_empty) {
+            return Err(());
+        }
+
+        let mut known = KnownCells::default();
+        let work: Vec<_> = problems.iter_mut().zip(&models).zip(&ranges).collect();
+        let backbones = parallel::map(work, |((problem, models), ranges)| {
+            backbone(problem, models, ranges, allowance)
+        });
+        for ((cells, _), (can_hold, can_lack)) in pieces.iter().zip(backbones) {
+            for (i, loc) in cells.iter().enumerate() {
+                if !can_hold[i] {
+                    known.empty.insert(*loc);
+                } else if !can_lack[i] {
+                    known.mines.insert(*loc);
+                }
+            }
+        }
+        if !free.is_empty() {
+            if reachable.iter().all(|total| *total == remaining) {
+                known.empty.extend(free.iter().cloned());
+            } else if reachable.iter().all(|total| *total + free.len() * capacity == remaining) {
+                known.mines.extend(free.iter().cloned());
+            }
+        }
+
+        // Whether the budget lasted through every search that the known cells depend on.
+        let complete = !allowance.is_exhausted();
+
+        // Every assignment is counted for the probabilities, which can take too long, in which case
+        // there just aren't any.
+        let work: Vec<_> = pieces.iter().zip(problems.iter_mut()).collect();
+        let enumerated = parallel::map(work, |((cells, _), problem)| {
+            problem.set_total(0, problem.cells() * capacity);
+            let problem: &Problem = problem;
+            let mut component = Component {
+                cells: cells.clone(),
+                arrangements: vec![],
+                mined: vec![],
+            };
+            let mut dpll = Dpll::new(problem, MAX_STEPS, allowance);
+            let finished = dpll.start() && dpll.search(&mut |assignment| {
+                let mines: usize = assignment.iter().sum();
+                if component.arrangements.len() <= mines {
+                    component.arrangements.resize(mines + 1, 0.0);
+                    component.mined.resize(mines + 1, vec![0.0; assignment.len()]);
+                }
+                component.arrangements[mines] += 1.0;
+                for (cell, n) in assignment.iter().enumerate() {
+                    if *n != 0 {
+                        component.mined[mines][cell] += 1.0;
+                    }
+                }
+                true
+            }).is_some();
+            (finished, dpll.steps, component)
+        });
+        let mut steps = 0;
+        let mut components = vec![];
+        for (finished, component_steps, component) in enumerated {
+            steps += component_steps;
+            if finished && steps <= MAX_STEPS {
+                components.push(component);
+            }
+        }
+        let probabilities = if components.len() == pieces.len() {
+            probability::weigh(&components, &free, remaining, capacity)
+        } else {
+            None
+        };
+
+        Ok(Solution {
+            known,
+            probabilities,
+            complete,
+        })
+    }
+}
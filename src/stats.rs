@@ -0,0 +1,265 @@
+//! Records of finished games, kept in a file so that they survive between runs.
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{Session, State},
+    opts::{Def, Preset},
+    util,
+};
+
+#[derive(Debug)]
+pub enum StatsError {
+    Io(io::Error),
+    Read(toml::de::Error),
+    Write(toml::ser::Error),
+}
+
+impl From<io::Error> for StatsError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for StatsError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Read(e)
+    }
+}
+
+impl From<toml::ser::Error> for StatsError {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::Write(e)
+    }
+}
+
+impl std::fmt::Display for StatsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => e.fmt(f),
+            Self::Read(e) => write!(f, "Stats file is corrupt: {}", e),
+            Self::Write(e) => write!(f, "Could not serialize stats: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+pub enum Outcome {
+    Won,
+    Lost,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Record {
+    // The board definition, in the same format accepted on the command line.
+    pub def: String,
+    pub capacity: u8,
+    pub lives: u32,
+    pub outcome: Outcome,
+    pub time_ms: u64,
+    pub bbbv: u64,
+    pub clicks: u64,
+    pub probes: u64,
+    // Played with help from the solver.
+    #[serde(default)]
+    pub assisted: bool,
+    // Hex encoded. Missing if the board wasn't randomly generated.
+    pub seed: Option<String>,
+}
+
+impl Record {
+    // Returns `None` if the game isn't over yet.
+    pub fn from_session(def: &Def, session: &Session) -> Option<Self> {
+        let outcome = match session.state() {
+            State::Playing => return None,
+            State::Won => Outcome::Won,
+            State::Lost => Outcome::Lost,
+        };
+        Some(Self {
+            def: def.key(),
+            capacity: session.board.capacity(),
+            lives: session.initial_lives(),
+            outcome,
+            time_ms: session.elapsed().as_millis() as u64,
+            bbbv: session.board.bbbv(),
+            clicks: session.clicks(),
+            probes: session.probes(),
+            assisted: session.is_assisted(),
+            seed: session.board.seed().map(|seed| util::to_hex(&seed)),
+        })
+    }
+
+    // Records are only comparable if they were played with the same rules.
+    fn is_variant(&self, def: &str, capacity: u8, lives: u32, assisted: bool) -> bool {
+        self.def == def && self.capacity == capacity && self.lives == lives && self.assisted == assisted
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StatsFile {
+    #[serde(default)]
+    games: Vec<Record>,
+}
+
+#[derive(Debug, PartialEq, Eq, Default, Copy, Clone)]
+pub struct Summary {
+    pub played: usize,
+    pub won: usize,
+    pub best_time: Option<Duration>,
+    pub current_streak: usize,
+    pub best_streak: usize,
+}
+
+impl Summary {
+    pub fn win_percentage(&self) -> f64 {
+        if self.played == 0 {
+            0.0
+        } else {
+            100.0 * self.won as f64 / self.played as f64
+        }
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let best_time = match self.best_time {
+            Some(t) => format!("{}.{:03}s", t.as_secs(), t.subsec_millis()),
+            None => "-".to_owned(),
+        };
+        write!(
+            f,
+            "{} played, {} won ({:.1}%), best time {}, streak {} (best {})",
+            self.played,
+            self.won,
+            self.win_percentage(),
+            best_time,
+            self.current_streak,
+            self.best_streak,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    games: Vec<Record>,
+}
+
+impl Store {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("minesweeper").join("stats.toml"))
+    }
+
+    // A missing file is treated as having no records.
+    pub fn load(path: &Path) -> Result<Self, StatsError> {
+        let games = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str::<StatsFile>(&text)?.games,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            games,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), StatsError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(&StatsFile { games: self.games.clone() })?;
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, record: Record) {
+        self.games.push(record);
+    }
+
+    pub fn summarize(&self, def: &str, capacity: u8, lives: u32, assisted: bool) -> Summary {
+        let mut summary = Summary::default();
+        for game in self.games.iter().filter(|g| g.is_variant(def, capacity, lives, assisted)) {
+            summary.played += 1;
+            match game.outcome {
+                Outcome::Won => {
+                    summary.won += 1;
+                    summary.current_streak += 1;
+                    summary.best_streak = summary.best_streak.max(summary.current_streak);
+                    let time = Duration::from_millis(game.time_ms);
+                    summary.best_time = Some(summary.best_time.map_or(time, |t| t.min(time)));
+                },
+                Outcome::Lost => summary.current_streak = 0,
+            }
+        }
+        summary
+    }
+
+    // Every preset is always listed, followed by anything else that has been played with the
+    // same rules.
+    pub fn report(&self, capacity: u8, lives: u32, assisted: bool) -> Vec<(String, Summary)> {
+        let presets = [Preset::Beginner, Preset::Intermediate, Preset::Advanced];
+        let mut defs: Vec<_> = presets.iter().map(|p| Def::Preset(*p).key()).collect();
+        for game in &self.games {
+            if game.is_variant(&game.def, capacity, lives, assisted) && !defs.contains(&game.def) {
+                defs.push(game.def.clone());
+            }
+        }
+        defs.into_iter()
+            .map(|def| {
+                let summary = self.summarize(&def, capacity, lives, assisted);
+                (def, summary)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Outcome, Record, Store, Summary};
+
+    fn record(outcome: Outcome, time_ms: u64) -> Record {
+        Record {
+            def: "beginner".to_owned(),
+            capacity: 1,
+            lives: 1,
+            outcome,
+            time_ms,
+            bbbv: 0,
+            clicks: 0,
+            probes: 0,
+            assisted: false,
+            seed: None,
+        }
+    }
+
+    #[test]
+    fn summarize_test() {
+        let mut store = Store::load("/nonexistent/stats.toml".as_ref())
+            .expect("missing file to be treated as empty.");
+        for (outcome, time_ms) in [
+            (Outcome::Won, 3000),
+            (Outcome::Won, 2000),
+            (Outcome::Lost, 1000),
+            (Outcome::Won, 4000),
+        ].iter().cloned() {
+            store.record(record(outcome, time_ms));
+        }
+        assert_eq!(store.summarize("beginner", 1, 1, false), Summary {
+            played: 4,
+            won: 3,
+            best_time: Some(Duration::from_millis(2000)),
+            current_streak: 1,
+            best_streak: 2,
+        });
+        assert_eq!(store.summarize("beginner", 1, 3, false), Summary::default());
+        assert_eq!(store.summarize("beginner", 1, 1, true), Summary::default());
+    }
+}
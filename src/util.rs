@@ -1,13 +1,17 @@
 use std::ops::Bound;
-use indexmap::IndexSet;
 
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
 
-pub fn split_sets<T: Eq + std::hash::Hash + Copy>(aa: IndexSet<T>, bb: IndexSet<T>) -> (IndexSet<T>, IndexSet<T>, IndexSet<T>) {
-    (
-        aa.difference(&bb).cloned().collect(),
-        aa.intersection(&bb).cloned().collect(),
-        bb.difference(&aa).cloned().collect(),
-    )
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect()
 }
 
 pub fn map_bound<T, S, F>(b: Bound<T>, f: F) -> Bound<S>